@@ -0,0 +1,43 @@
+//! Round-trip spread sweep over one market, rebuilding the simulator per quote (what the CLI used
+//! to do) versus reusing one loaded [`Simulator`].
+//!
+//! Run from the repository root after fetching a snapshot: `cargo bench --bench sweep`.
+
+use solana_pubkey::Pubkey;
+use solfi_sim::Simulator;
+use solfi_sim::constants::SOLFI_MARKETS;
+use solfi_sim::swap::SwapDirection;
+use std::time::{Duration, Instant};
+
+const SWEEP_SIZES: usize = 50;
+
+fn round_trip(sim: &Simulator, market: &Pubkey, usdc_in: f64) -> eyre::Result<()> {
+    let buy = sim.quote(market, SwapDirection::UsdcToSol, usdc_in)?;
+    if let Some(sol_out) = buy.out_amount {
+        sim.quote(market, SwapDirection::SolToUsdc, sol_out)?;
+    }
+    Ok(())
+}
+
+fn sweep(market: &Pubkey, mut sim: impl FnMut() -> eyre::Result<Simulator>) -> eyre::Result<Duration> {
+    let start = Instant::now();
+    for i in 1..=SWEEP_SIZES {
+        round_trip(&sim()?, market, i as f64 * 100.0)?;
+    }
+    Ok(start.elapsed())
+}
+
+fn main() -> eyre::Result<()> {
+    let market = SOLFI_MARKETS[1];
+
+    let rebuilt = sweep(&market, Simulator::load)?;
+
+    let warm = Simulator::load()?;
+    let reused = sweep(&market, || Ok(warm.clone()))?;
+
+    println!("{SWEEP_SIZES}-size round-trip sweep on {market}");
+    println!("  rebuild per size: {rebuilt:>10.2?}");
+    println!("  reuse simulator:  {reused:>10.2?}");
+    println!("  speedup:          {:>10.1}x", rebuilt.as_secs_f64() / reused.as_secs_f64());
+    Ok(())
+}
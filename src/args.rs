@@ -1,45 +1,262 @@
-use crate::swap::SwapDirection;
+use crate::cmd::{BookFormat, SpreadMethod};
 use clap::{Parser, Subcommand};
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::SnapshotFormat;
 use std::path::PathBuf;
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
+    /// Fetch the WSOL/USDC pools, or one market, with their vaults and mints into snapshots/
     FetchAccounts {
+        /// Market to fetch instead of the WSOL/USDC pools
         #[arg(long)]
         market: Option<String>,
-        #[arg(long = "market-token-quote")]
-        market_token_quote: Option<String>,
-        #[arg(long = "market-token-base")]
-        market_token_base: Option<String>,
+        /// Snapshot directory label; defaults to `wsol-usdc`, or the market address
+        #[arg(long)]
+        label: Option<String>,
+        #[arg(long, value_enum, default_value = "files")]
+        format: SnapshotFormat,
+    },
+
+    /// List every SolFi market on chain and save them to data/markets.json
+    DiscoverMarkets,
+
+    /// Import `solana account --output json` / validator `--account` files as a snapshot
+    ImportSnapshot {
+        dir: PathBuf,
+        /// SolFi program binary to store with the snapshot
+        #[arg(long)]
+        program: Option<PathBuf>,
+        #[arg(long)]
+        label: Option<String>,
+        /// Slot to record; defaults to the newest generated slot of the imported markets
+        #[arg(long)]
+        slot: Option<u64>,
+        #[arg(long, value_enum, default_value = "files")]
+        format: SnapshotFormat,
+    },
+
+    /// Write a snapshot out as `solana-test-validator` account files and a launch script
+    ExportSnapshot {
+        out_dir: PathBuf,
+        #[arg(long)]
+        snapshot: Option<String>,
+    },
+
+    /// Poll markets, vaults and mints and append every changed account state to recordings/
+    Record {
+        /// Markets to record; defaults to the WSOL/USDC pools
+        #[arg(long, value_delimiter = ',')]
+        market: Vec<String>,
+        /// Recording directory label; defaults to `wsol-usdc`, or the first market address
+        #[arg(long)]
+        label: Option<String>,
+        #[arg(long, default_value_t = 400)]
+        interval_ms: u64,
+        /// Start a new segment once the current one reaches this size
+        #[arg(long, default_value_t = 64)]
+        max_segment_mb: u64,
+        /// Delete the oldest segments once the recording outgrows this size
+        #[arg(long, default_value_t = 1024)]
+        max_total_mb: u64,
+        /// Stop after this many polls
+        #[arg(long)]
+        max_polls: Option<u64>,
+    },
+
+    /// Replay a recording state by state and write quotes or spreads per slot as CSV
+    Backtest {
+        /// Recording label under recordings/, or a path
+        recording: String,
+        /// USDC sizes: bought with for spreads, spent for usdc-to-sol quotes
+        #[arg(long, value_delimiter = ',')]
+        usdc_sizes: Vec<f64>,
+        /// SOL sizes sold for sol-to-usdc quotes
+        #[arg(long, value_delimiter = ',')]
+        sol_sizes: Vec<f64>,
+        /// Quote these directions one-way instead of computing round-trip spreads
+        #[arg(short, long, value_delimiter = ',')]
+        direction: Vec<SwapDirection>,
+        /// Only quote this market
+        #[arg(long)]
+        market: Option<String>,
+        /// Write the CSV here instead of stdout
+        #[arg(long)]
+        csv: Option<PathBuf>,
+    },
+
+    /// Print each WSOL/USDC pool's cutoff and generated slots
+    Cutoffs {
+        #[arg(long)]
+        snapshot: Option<String>,
+    },
+
+    /// Decode a market account from a snapshot and hexdump the bytes the decoder does not cover
+    InspectMarket {
+        /// Market account address
+        market: String,
+        #[arg(long)]
+        snapshot: Option<String>,
     },
 
-    Cutoffs,
+    /// Show which bytes of a market account changed between two snapshots
+    DiffMarket {
+        /// Market account address
+        market: String,
+        /// Snapshot name under snapshots/, or a snapshot directory
+        before: String,
+        /// Snapshot name under snapshots/, or a snapshot directory
+        after: String,
+    },
 
+    /// Buy SOL with USDC and sell it back on each pool, and report the round-trip spread
     Spreads {
+        /// USDC to buy SOL with when `--sizes` is not given
         starting_usdc: f64,
+        /// USDC sizes to sweep instead of `starting_usdc`
         #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(f64))]
         sizes: Option<Vec<f64>>,
+        /// Write the CSV here instead of printing
         #[arg(long)]
         csv: Option<PathBuf>,
+        /// Only quote this market
+        #[arg(long)]
+        market: Option<String>,
+        /// Slot to simulate at; defaults to the oldest generated slot of the pools
+        #[arg(long)]
+        slot: Option<u64>,
+        #[arg(long)]
+        snapshot: Option<String>,
+        /// Spread methodologies to report: printed side by side, one CSV row each
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "round-trip")]
+        method: Vec<SpreadMethod>,
+        /// SOL traded each way to find the mid the independent method sizes its sell leg at
+        #[arg(long, default_value_t = 0.01)]
+        probe: f64,
+    },
+
+    /// Binary-search the input that receives exactly `amount` out
+    QuoteExactOut {
+        /// Output to receive, in the output token
+        #[arg(short, long)]
+        amount: f64,
+        #[arg(short, long, default_value_t = SwapDirection::UsdcToSol)]
+        direction: SwapDirection,
+        /// Only quote this market; defaults to the WSOL/USDC pools
+        #[arg(long)]
+        market: Option<String>,
+        #[arg(short, long)]
+        slot: Option<u64>,
+        #[arg(long)]
+        snapshot: Option<String>,
+    },
+
+    /// Largest trade whose average price stays within a slippage budget or limit price
+    MaxSize {
+        #[arg(short, long, default_value_t = SwapDirection::SolToUsdc)]
+        direction: SwapDirection,
+        /// Worst average price allowed, in bps from mid
+        #[arg(long, required_unless_present = "limit_price", conflicts_with = "limit_price")]
+        max_slippage_bps: Option<f64>,
+        /// Worst average price allowed: a floor when selling SOL, a ceiling when buying it
+        #[arg(long)]
+        limit_price: Option<f64>,
+        /// Only size this market; defaults to each WSOL/USDC pool and all of them combined
         #[arg(long)]
         market: Option<String>,
-        #[arg(long = "market-token-quote")]
-        market_token_quote: Option<String>,
-        #[arg(long = "market-token-base")]
-        market_token_base: Option<String>,
+        /// SOL traded to find mid; also the smallest size tried
+        #[arg(long, default_value_t = 0.01)]
+        probe: f64,
+        /// Parts a combined order is split into across pools
+        #[arg(long, default_value_t = 10)]
+        chunks: u32,
+        #[arg(short, long)]
+        slot: Option<u64>,
         #[arg(long)]
+        snapshot: Option<String>,
+    },
+
+    /// Split an order across the WSOL/USDC pools for the best total output
+    Route {
+        #[arg(short, long)]
+        amount: f64,
+        #[arg(short, long, default_value_t = SwapDirection::SolToUsdc)]
+        direction: SwapDirection,
+        /// Parts the order is split into; more is finer but simulates more swaps
+        #[arg(long, default_value_t = 20)]
+        chunks: u32,
+        #[arg(short, long)]
         slot: Option<u64>,
+        #[arg(long)]
+        snapshot: Option<String>,
+    },
+
+    /// Run swaps one after another on the same state, each seeing the previous ones' effects
+    Sequence {
+        /// Swaps as direction:amount[:market], e.g. sol-to-usdc:10
+        #[arg(long, value_delimiter = ',', required = true)]
+        swap: Vec<String>,
+        /// Market for swaps that do not name one
+        #[arg(long)]
+        market: Option<String>,
+        /// Run the list of swaps this many times, e.g. for the slices of a TWAP order
+        #[arg(long, default_value_t = 1)]
+        repeat: u32,
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        #[arg(long)]
+        snapshot: Option<String>,
+    },
+
+    /// Repeat a swap while warping from each market's generated slot past its cutoff slot
+    Staleness {
+        #[arg(short, long)]
+        amount: Option<f64>,
+        #[arg(short, long, default_value_t = SwapDirection::SolToUsdc)]
+        direction: SwapDirection,
+        /// Only sweep this market; defaults to the WSOL/USDC pools
+        #[arg(long)]
+        market: Option<String>,
+        /// Slots to advance per step
+        #[arg(long, default_value_t = 10)]
+        step: u64,
+        /// How far past the cutoff slot to keep going
+        #[arg(long, default_value_t = 50)]
+        past_cutoff: u64,
+        /// Write every step here as CSV
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        #[arg(long)]
+        snapshot: Option<String>,
+    },
+
+    /// Synthetic depth ladder per pool and across all pools
+    Book {
+        /// Grid of cumulative sizes in the base token, on both sides
+        #[arg(long, value_delimiter = ',', default_value = "1,5,10,25,50,100,250")]
+        sizes: Vec<f64>,
+        #[arg(long)]
+        market: Option<String>,
+        #[arg(long, value_enum, default_value = "table")]
+        format: BookFormat,
+        #[arg(long)]
+        snapshot: Option<String>,
     },
 
+    /// Quote one swap on each WSOL/USDC pool and print the results as CSV
     Simulate {
+        /// Input amount; defaults to 10 SOL or 1000 USDC
         #[arg(short, long)]
         amount: Option<f64>,
         #[arg(short, long, default_value_t = SwapDirection::SolToUsdc)]
         direction: SwapDirection,
         #[arg(short, long)]
         slot: Option<u64>,
+        /// Leave out pools whose swap failed
         #[arg(long)]
         ignore_errors: bool,
+        #[arg(long)]
+        snapshot: Option<String>,
     },
 }
 
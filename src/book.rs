@@ -0,0 +1,182 @@
+use crate::Simulator;
+use crate::swap::SwapDirection;
+use serde::Serialize;
+use solana_pubkey::Pubkey;
+
+/// Market name of the levels [`aggregate`] merges from several pools.
+pub const AGGREGATE_MARKET: &str = "all";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// One step of a synthetic L2 ladder: the base traded between the previous grid size and this
+/// one, at its marginal price.
+#[derive(Serialize, Debug, Clone)]
+pub struct BookLevel {
+    pub market: String,
+    pub side: Side,
+    /// Quote per base for this level's `size` alone.
+    pub price: f64,
+    pub size: f64,
+    pub cumulative_size: f64,
+    /// Quote per base over everything from the top of the book down to this level.
+    pub average_price: f64,
+}
+
+/// Builds a ladder for one market from quotes at each of `sizes` (in base tokens) on both sides.
+/// Bids sell the base; asks spend what the best bid pays for the base, so their cumulative size
+/// is whatever base that buys. A side ends at the first size that fails.
+pub fn depth(sim: &Simulator, market: &Pubkey, sizes: &[f64]) -> eyre::Result<Vec<BookLevel>> {
+    let mut sizes = sizes.iter().copied().filter(|s| *s > 0.0).collect::<Vec<_>>();
+    sizes.sort_by(f64::total_cmp);
+
+    let mut levels: Vec<BookLevel> = vec![];
+    for side in [Side::Bid, Side::Ask] {
+        // asks are sized in quote at the top bid; with no bid there is nothing to size them by
+        let reference = levels.first().map(|l| l.price).unwrap_or_default();
+        let (mut base, mut quote) = (0.0, 0.0);
+        for &size in &sizes {
+            let (next_base, next_quote) = match side {
+                Side::Bid => {
+                    match sim.quote_market(market, SwapDirection::SolToUsdc, size)?.out_amount {
+                        Some(out) => (size, out),
+                        None => break,
+                    }
+                }
+                Side::Ask if reference <= 0.0 => break,
+                Side::Ask => {
+                    let spend = size * reference;
+                    match sim.quote_market(market, SwapDirection::UsdcToSol, spend)?.out_amount {
+                        Some(out) => (out, spend),
+                        None => break,
+                    }
+                }
+            };
+            if next_base <= base {
+                continue;
+            }
+            levels.push(BookLevel {
+                market: market.to_string(),
+                side,
+                price: (next_quote - quote) / (next_base - base),
+                size: next_base - base,
+                cumulative_size: next_base,
+                average_price: next_quote / next_base,
+            });
+            (base, quote) = (next_base, next_quote);
+        }
+    }
+    Ok(levels)
+}
+
+/// Merges the ladders of several pools into one, as a taker sweeping every pool would: each side
+/// takes the best-priced next level among the pools, and a pool's levels only in order of depth,
+/// since its deeper levels cannot be reached before its shallower ones.
+pub fn aggregate(levels: &[BookLevel]) -> Vec<BookLevel> {
+    let mut merged = vec![];
+    for side in [Side::Ask, Side::Bid] {
+        // each pool's levels on this side, deepest first so the next one to take is at the end
+        let mut pools: Vec<Vec<&BookLevel>> = vec![];
+        for level in levels.iter().filter(|l| l.side == side) {
+            match pools.iter_mut().find(|p| p[0].market == level.market) {
+                Some(pool) => pool.push(level),
+                None => pools.push(vec![level]),
+            }
+        }
+        for pool in &mut pools {
+            pool.sort_by(|a, b| b.cumulative_size.total_cmp(&a.cumulative_size));
+        }
+
+        let (mut base, mut quote) = (0.0, 0.0);
+        while let Some(pool) = pools
+            .iter_mut()
+            .filter(|p| !p.is_empty())
+            .min_by(|a, b| {
+                let (a, b) = (a[a.len() - 1].price, b[b.len() - 1].price);
+                match side {
+                    Side::Ask => a.total_cmp(&b),
+                    Side::Bid => b.total_cmp(&a),
+                }
+            })
+        {
+            let level = pool.pop().unwrap();
+            base += level.size;
+            quote += level.price * level.size;
+            merged.push(BookLevel {
+                market: AGGREGATE_MARKET.to_string(),
+                side,
+                price: level.price,
+                size: level.size,
+                cumulative_size: base,
+                average_price: quote / base,
+            });
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a ladder for `market` from (price, size) levels, shallowest first
+    fn ladder(market: &str, side: Side, levels: &[(f64, f64)]) -> Vec<BookLevel> {
+        let (mut base, mut quote) = (0.0, 0.0);
+        levels
+            .iter()
+            .map(|&(price, size)| {
+                base += size;
+                quote += price * size;
+                BookLevel {
+                    market: market.to_string(),
+                    side,
+                    price,
+                    size,
+                    cumulative_size: base,
+                    average_price: quote / base,
+                }
+            })
+            .collect()
+    }
+
+    fn prices(levels: &[BookLevel], side: Side) -> Vec<f64> {
+        levels.iter().filter(|l| l.side == side).map(|l| l.price).collect()
+    }
+
+    #[test]
+    fn takes_the_best_priced_levels_first() {
+        let levels = [
+            ladder("a", Side::Bid, &[(100.0, 1.0), (98.0, 1.0)]),
+            ladder("b", Side::Bid, &[(99.0, 2.0), (97.0, 1.0)]),
+            ladder("a", Side::Ask, &[(101.0, 1.0), (103.0, 1.0)]),
+            ladder("b", Side::Ask, &[(102.0, 1.0)]),
+        ]
+        .concat();
+        let merged = aggregate(&levels);
+
+        assert_eq!(prices(&merged, Side::Bid), vec![100.0, 99.0, 98.0, 97.0]);
+        assert_eq!(prices(&merged, Side::Ask), vec![101.0, 102.0, 103.0]);
+        assert!(merged.iter().all(|l| l.market == AGGREGATE_MARKET));
+        let last_bid = merged.iter().rfind(|l| l.side == Side::Bid).unwrap();
+        assert_eq!(last_bid.cumulative_size, 5.0);
+        assert!((last_bid.average_price - (100.0 + 198.0 + 98.0 + 97.0) / 5.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn never_takes_a_deeper_level_of_a_pool_before_a_shallower_one() {
+        // pool a's marginal ask improves with depth, so its second level is the cheapest overall
+        let levels = [
+            ladder("a", Side::Ask, &[(103.0, 1.0), (100.0, 1.0)]),
+            ladder("b", Side::Ask, &[(101.0, 1.0), (104.0, 1.0)]),
+        ]
+        .concat();
+        let merged = aggregate(&levels);
+
+        assert_eq!(prices(&merged, Side::Ask), vec![101.0, 103.0, 100.0, 104.0]);
+        assert_eq!(merged.last().unwrap().cumulative_size, 4.0);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::cmd::spreads::{SpreadMethod, compute_single_market_row};
+use csv::Writer;
+use eyre::WrapErr;
+use solana_pubkey::Pubkey;
+use solfi_sim::constants::SOLFI_PROGRAM;
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::{RecordedAccount, Recording};
+use solfi_sim::Simulator;
+use std::fs;
+use std::io::{stdout, Write};
+use std::path::Path;
+
+/// A one-leg quote ([`solfi_sim::SwapResult`]) at one recorded state.
+#[derive(serde::Serialize)]
+struct QuoteRow {
+    slot: u64,
+    generated_slot: Option<u64>,
+    market: String,
+    direction: String,
+    in_amount: f64,
+    out_amount: Option<f64>,
+    /// Quote tokens per base token, whichever way the swap went.
+    price: Option<f64>,
+    error: Option<String>,
+    error_name: Option<String>,
+    compute_units: u64,
+}
+
+/// A round-trip spread ([`crate::cmd::spreads::SpreadRow`]) at one recorded state.
+#[derive(serde::Serialize)]
+struct SpreadPoint {
+    slot: u64,
+    generated_slot: Option<u64>,
+    amount_usdc: f64,
+    market: String,
+    buy_price: f64,
+    sell_price: f64,
+    spread_usd: f64,
+    spread_bps: f64,
+    buy_compute_units: u64,
+    sell_compute_units: u64,
+}
+
+/// Replays a recording slot by slot and quotes every market at each recorded state: round-trip
+/// spreads for each of `usdc_sizes`, or one-leg quotes in each of `directions` when any are given,
+/// selling each of `sol_sizes` or spending each of `usdc_sizes`. Quotes run at each market's
+/// generated slot, as `simulate` does.
+pub fn backtest(
+    recording: &Recording,
+    usdc_sizes: &[f64],
+    sol_sizes: &[f64],
+    directions: &[SwapDirection],
+    market: Option<Pubkey>,
+    csv: Option<&Path>,
+) -> eyre::Result<()> {
+    let sizes = |direction| match direction {
+        SwapDirection::SolToUsdc => sol_sizes,
+        SwapDirection::UsdcToSol => usdc_sizes,
+    };
+    eyre::ensure!(!directions.is_empty() || !usdc_sizes.is_empty(), "round-trip spreads need --usdc-sizes");
+    for &direction in directions {
+        let flag = match direction {
+            SwapDirection::SolToUsdc => "--sol-sizes",
+            SwapDirection::UsdcToSol => "--usdc-sizes",
+        };
+        eyre::ensure!(!sizes(direction).is_empty(), "{direction} quotes need {flag}");
+    }
+
+    let records = recording.read_all()?;
+    eyre::ensure!(!records.is_empty(), "recording {} is empty", recording.dir().display());
+
+    let mut markets = records
+        .iter()
+        .filter(|r| r.account.account.owner == SOLFI_PROGRAM)
+        .map(|r| r.account.address)
+        .filter(|address| market.is_none_or(|m| m == *address))
+        .collect::<Vec<_>>();
+    markets.sort();
+    markets.dedup();
+    eyre::ensure!(!markets.is_empty(), "no matching SolFi markets in the recording");
+
+    recording.verify_program(records.iter().map(|r| r.slot).min().unwrap_or_default());
+    let program_path = recording.program_path();
+    let program = fs::read(&program_path).wrap_err_with(|| format!("failed to read {}", program_path.display()))?;
+
+    let out: Box<dyn Write> = match csv {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(stdout()),
+    };
+    let mut w = Writer::from_writer(out);
+
+    let mut sim: Option<Simulator> = None;
+    let mut states = 0;
+    for group in records.chunk_by(|a, b| a.slot == b.slot) {
+        let slot = group[0].slot;
+        let accounts = group.iter().map(|r: &RecordedAccount| r.account.clone());
+        match sim.as_mut() {
+            Some(sim) => sim.set_accounts(accounts)?,
+            None => sim = Some(Simulator::new(accounts.collect(), &program, None)?),
+        }
+        let sim = sim.as_ref().expect("set above");
+        states += 1;
+
+        for market in &markets {
+            let generated_slot = sim.generated_slot(market);
+            if directions.is_empty() {
+                for &size in usdc_sizes {
+                    if let Some(row) = compute_single_market_row(sim, size, &market.to_string(), SpreadMethod::RoundTrip, None)? {
+                        w.serialize(SpreadPoint {
+                            slot,
+                            generated_slot,
+                            amount_usdc: row.amount_usdc,
+                            market: row.market,
+                            buy_price: row.buy_price,
+                            sell_price: row.sell_price,
+                            spread_usd: row.spread_usd,
+                            spread_bps: row.spread_bps,
+                            buy_compute_units: row.buy_compute_units,
+                            sell_compute_units: row.sell_compute_units,
+                        })?;
+                    }
+                }
+            }
+            for &direction in directions {
+                for &size in sizes(direction) {
+                    let r = sim.quote_market(market, direction, size)?;
+                    let price = r.average_price(direction);
+                    w.serialize(QuoteRow {
+                        slot,
+                        generated_slot,
+                        market: r.market,
+                        direction: direction.to_string(),
+                        in_amount: r.in_amount,
+                        out_amount: r.out_amount,
+                        price,
+                        error: r.error,
+                        error_name: r.error_name,
+                        compute_units: r.compute_units,
+                    })?;
+                }
+            }
+        }
+    }
+    w.flush()?;
+
+    tracing::info!("Replayed {states} recorded states of {} markets", markets.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::program_pack::Pack;
+    use solfi_sim::types::{AccountWithAddress, RecordingWriter, Snapshot, SolfiMarket};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    const MARKET: &str = "DH4xmaWDnTzKXehVaPSNy9tMKJxnYL5Mo5U3oTHFtNYJ";
+
+    // a recording of the data/ accounts at slot 100, then of the market's SOL vault emptied at 101
+    fn two_states(dir: &tempfile::TempDir) -> Recording {
+        let market = Pubkey::from_str(MARKET).unwrap();
+        let accounts = Snapshot::open("data").unwrap().read_accounts().unwrap();
+        let decoded = SolfiMarket::from_account(&accounts.iter().find(|a| a.address == market).unwrap().account).unwrap();
+        let mut vault = accounts.iter().find(|a| a.address == decoded.base_vault).unwrap().clone();
+        let mut state = spl_token::state::Account::unpack(&vault.account.data).unwrap();
+        state.amount = 0;
+        spl_token::state::Account::pack(state, &mut vault.account.data).unwrap();
+
+        let recording = Recording::open(dir.path().to_str().unwrap()).unwrap();
+        let mut writer = RecordingWriter::new(recording, u64::MAX, u64::MAX).unwrap();
+        let records = |slot, accounts: Vec<AccountWithAddress>| {
+            accounts.into_iter().map(|account| RecordedAccount { slot, account }).collect::<Vec<_>>()
+        };
+        writer.append(100, &records(100, accounts)).unwrap();
+        writer.append(101, &records(101, vec![vault])).unwrap();
+        Recording::open(dir.path().to_str().unwrap()).unwrap()
+    }
+
+    fn run(usdc_sizes: &[f64], sol_sizes: &[f64], directions: &[SwapDirection]) -> Vec<HashMap<String, String>> {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("backtest.csv");
+        let market = Some(Pubkey::from_str(MARKET).unwrap());
+        backtest(&two_states(&dir), usdc_sizes, sol_sizes, directions, market, Some(&csv)).unwrap();
+        csv::Reader::from_path(&csv).unwrap().deserialize().collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn quotes_each_direction_in_its_own_unit_at_every_state() {
+        let rows = run(&[100.0], &[1.0], &[SwapDirection::SolToUsdc, SwapDirection::UsdcToSol]);
+        let summary = rows
+            .iter()
+            .map(|r| (r["slot"].as_str(), r["direction"].as_str(), r["in_amount"].as_str(), r["error"].is_empty()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                ("100", "sol-to-usdc", "1.0", true),
+                ("100", "usdc-to-sol", "100.0", true),
+                ("101", "sol-to-usdc", "1.0", true),
+                // nothing left to buy once the vault is empty
+                ("101", "usdc-to-sol", "100.0", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_round_trip_spreads_per_state() {
+        let rows = run(&[100.0, 1000.0], &[], &[]);
+        let slots = rows.iter().map(|r| (r["slot"].as_str(), r["amount_usdc"].as_str())).collect::<Vec<_>>();
+        // the buy leg fails once the vault is empty, so slot 101 has no round trip
+        assert_eq!(slots, [("100", "100.0"), ("100", "1000.0")]);
+    }
+
+    #[test]
+    fn needs_sizes_in_the_unit_of_each_direction() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = two_states(&dir);
+        let err = backtest(&recording, &[100.0], &[], &[SwapDirection::SolToUsdc], None, None).unwrap_err();
+        assert_eq!(err.to_string(), "sol-to-usdc quotes need --sol-sizes");
+        assert!(backtest(&recording, &[], &[1.0], &[], None, None).is_err());
+    }
+}
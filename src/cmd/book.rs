@@ -0,0 +1,72 @@
+use csv::Writer;
+use solana_pubkey::Pubkey;
+use solfi_sim::Simulator;
+use solfi_sim::book::{BookLevel, Side, aggregate, depth};
+use solfi_sim::types::Snapshot;
+use std::io::stdout;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum BookFormat {
+    #[default]
+    Table,
+    Csv,
+    Json,
+}
+
+/// Prints a synthetic depth ladder for each WSOL/USDC pool (all quoted at the same slot) and for
+/// the pools combined, or for `market` alone.
+pub fn book(
+    snapshot: &Snapshot,
+    sizes: &[f64],
+    market: Option<Pubkey>,
+    format: BookFormat,
+) -> eyre::Result<()> {
+    let (sim, markets) = Simulator::load_markets(snapshot, market, None)?;
+
+    let mut levels = vec![];
+    for market in &markets {
+        match depth(&sim, market, sizes) {
+            Ok(ladder) => levels.extend(ladder),
+            Err(e) => tracing::warn!("Skipping {market}: {e:#}"),
+        }
+    }
+    if markets.len() > 1 {
+        let merged = aggregate(&levels);
+        levels.extend(merged);
+    }
+
+    match format {
+        BookFormat::Table => print_ladders(&levels),
+        BookFormat::Csv => {
+            let mut w = Writer::from_writer(stdout());
+            for level in &levels {
+                w.serialize(level)?;
+            }
+            w.flush()?;
+        }
+        BookFormat::Json => println!("{}", serde_json::to_string_pretty(&levels)?),
+    }
+    Ok(())
+}
+
+// asks from the deepest level up to the best, then bids from the best down
+fn print_ladders(levels: &[BookLevel]) {
+    let mut markets = levels.iter().map(|l| l.market.as_str()).collect::<Vec<_>>();
+    markets.dedup();
+    for market in markets {
+        println!("== {market} ==");
+        println!(
+            "  {:<4} {:>12} {:>14} {:>14} {:>12}",
+            "side", "price", "size", "cumulative", "avg price"
+        );
+        let of = |side| levels.iter().filter(move |l| l.market == market && l.side == side);
+        for l in of(Side::Ask).collect::<Vec<_>>().into_iter().rev().chain(of(Side::Bid)) {
+            let side = if l.side == Side::Ask { "ask" } else { "bid" };
+            println!(
+                "  {side:<4} {:>12.4} {:>14.6} {:>14.6} {:>12.4}",
+                l.price, l.size, l.cumulative_size, l.average_price
+            );
+        }
+        println!();
+    }
+}
@@ -1,26 +1,20 @@
-use crate::constants::SOLFI_MARKETS;
-use crate::types::{AccountWithAddress, FetchMetadata};
-use crate::utils::u64_at_offset;
 use solana_pubkey::Pubkey;
+use solfi_sim::types::{MarketRegistry, Snapshot, SolfiMarket};
 
-const CUTOFF_OFFSET: usize = 488;
-const GEN_OFFSET: usize = 464;
-
-fn mm_metadata(market: &Pubkey) -> eyre::Result<(u64, u64)> {
-    let acct = AccountWithAddress::read_account(format!("data/account_{market}.json").into())?;
-    Ok((
-        u64_at_offset(acct.account.data.as_slice(), CUTOFF_OFFSET)?,
-        u64_at_offset(acct.account.data.as_slice(), GEN_OFFSET)?,
-    ))
+fn mm_metadata(snapshot: &Snapshot, market: &Pubkey) -> eyre::Result<SolfiMarket> {
+    let acct = snapshot.read_account(market)?;
+    SolfiMarket::from_account(&acct.account)
 }
 
-pub fn display_cutoffs() {
-    if let Some(metadata) = FetchMetadata::read() {
-        println!("== {metadata} ==");
+pub fn display_cutoffs(snapshot: &Snapshot) -> eyre::Result<()> {
+    if let Some(metadata) = snapshot.metadata() {
+        println!("== {metadata} ({}) ==", snapshot.name());
     }
-    for market in SOLFI_MARKETS {
-        if let Ok((cutoff, generated)) = mm_metadata(market) {
-            println!("{market} cutoff slot={cutoff}, generated slot={generated}");
+    for market in MarketRegistry::wsol_usdc_markets()? {
+        match mm_metadata(snapshot, &market) {
+            Ok(m) => println!("{market} cutoff slot={}, generated slot={}", m.cutoff_slot, m.generated_slot),
+            Err(e) => println!("{market} unreadable: {e:#}"),
         }
     }
+    Ok(())
 }
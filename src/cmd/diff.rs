@@ -0,0 +1,97 @@
+use solana_pubkey::Pubkey;
+use solfi_sim::types::{MARKET_FIELDS, Snapshot};
+use std::ops::Range;
+
+const WORD: usize = 8;
+
+/// Compares one account between two snapshots, in whichever format each stores its accounts.
+pub fn diff_market(before: &Snapshot, after: &Snapshot, market: Pubkey) -> eyre::Result<()> {
+    let (before_name, after_name) = (before.name(), after.name());
+    let before = before.read_account(&market)?;
+    let after = after.read_account(&market)?;
+
+    println!("== diff {market} ({before_name} -> {after_name}) ==");
+    if before.account.lamports != after.account.lamports {
+        println!("lamports: {} -> {}", before.account.lamports, after.account.lamports);
+    }
+    if before.account.owner != after.account.owner {
+        println!("owner: {} -> {}", before.account.owner, after.account.owner);
+    }
+    let (old, new) = (before.account.data.as_slice(), after.account.data.as_slice());
+    if old.len() != new.len() {
+        println!("data length: {} -> {} (comparing the common prefix)", old.len(), new.len());
+    }
+
+    let ranges = changed_ranges(old, new);
+    if ranges.is_empty() {
+        println!("no data changes");
+        return Ok(());
+    }
+    let changed_bytes: usize =
+        ranges.iter().map(|r| (r.start..r.end).filter(|i| old[*i] != new[*i]).count()).sum();
+    println!("{changed_bytes} bytes changed in {} ranges\n", ranges.len());
+
+    for range in ranges {
+        let fields = MARKET_FIELDS
+            .iter()
+            .filter(|(_, f)| f.start < range.end && range.start < f.end)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        let label = if fields.is_empty() { String::new() } else { format!("  {}", fields.join(", ")) };
+        println!("[{:#06x}..{:#06x}) {} bytes{label}", range.start, range.end, range.len());
+
+        if range.len() == 32 {
+            let as_pubkey = |d: &[u8]| Pubkey::new_from_array(d[range.clone()].try_into().unwrap());
+            println!("  pubkey  {} -> {}", as_pubkey(old), as_pubkey(new));
+        }
+        for start in range.clone().step_by(WORD) {
+            let word = start..start + WORD;
+            if old[word.clone()] == new[word.clone()] {
+                continue;
+            }
+            println!("  {:#06x}  {}", start, interpret(&old[word.clone()], &new[word]));
+        }
+    }
+
+    Ok(())
+}
+
+/// Word-aligned ranges of the common prefix that differ, with adjacent changed words merged.
+fn changed_ranges(old: &[u8], new: &[u8]) -> Vec<Range<usize>> {
+    let len = old.len().min(new.len()) / WORD * WORD;
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for start in (0..len).step_by(WORD) {
+        if old[start..start + WORD] == new[start..start + WORD] {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = start + WORD,
+            _ => ranges.push(start..start + WORD),
+        }
+    }
+    ranges
+}
+
+// every word is shown as u64; i64 and f64 readings are added only when they look meaningful
+fn interpret(old: &[u8], new: &[u8]) -> String {
+    let (a, b) = (
+        u64::from_le_bytes(old.try_into().unwrap()),
+        u64::from_le_bytes(new.try_into().unwrap()),
+    );
+    let mut out = format!("u64 {a} -> {b} ({:+})", b as i128 - a as i128);
+
+    let (ia, ib) = (a as i64, b as i64);
+    if (ia < 0 || ib < 0) && ia.unsigned_abs() < 1 << 32 && ib.unsigned_abs() < 1 << 32 {
+        out.push_str(&format!("  i64 {ia} -> {ib}"));
+    }
+
+    let (fa, fb) = (f64::from_bits(a), f64::from_bits(b));
+    if plausible_f64(fa) && plausible_f64(fb) {
+        out.push_str(&format!("  f64 {fa} -> {fb}"));
+    }
+    out
+}
+
+fn plausible_f64(v: f64) -> bool {
+    v == 0.0 || (v.is_normal() && (1e-9..1e15).contains(&v.abs()))
+}
@@ -0,0 +1,136 @@
+use crate::cmd::rpc::PinnedClient;
+use eyre::Result;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_api::config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_rpc_client_api::filter::RpcFilterType;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solfi_sim::constants::SOLFI_PROGRAM;
+use solfi_sim::types::{MARKET_ACCOUNT_LEN, MarketEntry, MarketRegistry, SolfiMarket, pair_symbol};
+use std::collections::HashMap;
+
+pub async fn discover_markets(rpc_url: String) -> Result<()> {
+    let markets = find_markets(rpc_url).await?;
+    for m in &markets {
+        println!("{} {}", m.market, m.pair);
+    }
+
+    let registry = MarketRegistry { markets };
+    registry.save_to_file()?;
+    Ok(())
+}
+
+/// Every SolFi program account that decodes as a market and whose base vault exists, sorted by
+/// pair and address.
+async fn find_markets(rpc_url: String) -> Result<Vec<MarketEntry>> {
+    let client = RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
+
+    tracing::info!("Fetching all SolFi market accounts…");
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::DataSize(MARKET_ACCOUNT_LEN as u64)]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let accounts = client.get_program_accounts_with_config(&SOLFI_PROGRAM, config).await?;
+
+    let mut decoded = vec![];
+    for (address, account) in &accounts {
+        match SolfiMarket::from_account(account) {
+            Ok(market) => decoded.push((*address, market)),
+            Err(err) => tracing::warn!("Skipping {address}: {err}"),
+        }
+    }
+
+    let vaults = decoded.iter().map(|(_, m)| m.base_vault).collect::<Vec<_>>();
+    let pinned = PinnedClient::new(rpc_url, CommitmentConfig::confirmed());
+    let vault_accounts = vaults
+        .iter()
+        .zip(pinned.get_multiple_accounts(&vaults).await?)
+        .filter_map(|(address, account)| Some((*address, account?)))
+        .collect::<HashMap<_, _>>();
+
+    let mut markets = vec![];
+    for (address, market) in decoded {
+        let Some(vault) = vault_accounts.get(&market.base_vault) else {
+            tracing::warn!("Skipping {address}: base vault {} not found", market.base_vault);
+            continue;
+        };
+        markets.push(MarketEntry {
+            market: address,
+            pair: pair_symbol(&market.base_mint, &market.quote_mint),
+            base_mint: market.base_mint,
+            quote_mint: market.quote_mint,
+            base_vault: market.base_vault,
+            quote_vault: market.quote_vault,
+            token_program: vault.owner,
+        });
+    }
+    markets.sort_by(|a, b| a.pair.cmp(&b.pair).then(a.market.cmp(&b.market)));
+
+    tracing::info!("Discovered {} SolFi markets ({} program accounts)", markets.len(), accounts.len());
+    Ok(markets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::mock_rpc::{MockRpc, data_account, multiple_accounts, ui_account, with_context};
+    use serde_json::{Value, json};
+    use solana_account::Account;
+    use solana_pubkey::Pubkey;
+    use solfi_sim::constants::{USDC, WSOL};
+    use solfi_sim::types::AccountWithAddress;
+    use std::str::FromStr;
+
+    const DH4X: &str = "DH4xmaWDnTzKXehVaPSNy9tMKJxnYL5Mo5U3oTHFtNYJ";
+    const CAPH: &str = "CAPhoEse9xEH95XmdnJjYrZdNCA8xfUWdy3aWymHa1Vj";
+
+    #[tokio::test]
+    async fn discovers_markets_and_skips_other_program_accounts() {
+        let markets = [DH4X, CAPH].map(|m| data_account(&Pubkey::from_str(m).unwrap()));
+        let vaults = markets
+            .iter()
+            .map(|m| data_account(&SolfiMarket::from_account(&m.account).unwrap().base_vault))
+            .collect::<Vec<_>>();
+        // owned by SolFi and market-sized, but with no mints or vaults set
+        let not_a_market = AccountWithAddress {
+            address: Pubkey::new_unique(),
+            account: Account {
+                lamports: 1,
+                data: vec![0; MARKET_ACCOUNT_LEN],
+                owner: SOLFI_PROGRAM,
+                ..Account::default()
+            },
+        };
+
+        let program_accounts = markets
+            .iter()
+            .chain([&not_a_market])
+            .map(|a| json!({ "pubkey": a.address.to_string(), "account": ui_account(a) }))
+            .collect::<Vec<_>>();
+        let rpc = MockRpc::start(move |method, params| match method {
+            "getProgramAccounts" => {
+                assert_eq!(params[0], SOLFI_PROGRAM.to_string());
+                Value::Array(program_accounts.clone())
+            }
+            "getMultipleAccounts" => with_context(1, multiple_accounts(&vaults, params)),
+            _ => panic!("unexpected {method}"),
+        })
+        .await;
+
+        let found = find_markets(rpc.url()).await.unwrap();
+        let mut expected = [DH4X, CAPH].map(|m| Pubkey::from_str(m).unwrap());
+        expected.sort();
+        assert_eq!(found.iter().map(|m| m.market).collect::<Vec<_>>(), expected);
+        for entry in &found {
+            let decoded = SolfiMarket::from_account(&data_account(&entry.market).account).unwrap();
+            assert_eq!(entry.pair, "SOL/USDC");
+            assert_eq!((entry.base_mint, entry.quote_mint), (WSOL, USDC));
+            assert_eq!((entry.base_vault, entry.quote_vault), (decoded.base_vault, decoded.quote_vault));
+            assert_eq!(entry.token_program, spl_token::id());
+        }
+    }
+}
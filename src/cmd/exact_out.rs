@@ -0,0 +1,28 @@
+use csv::WriterBuilder;
+use solana_pubkey::Pubkey;
+use solfi_sim::Simulator;
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::Snapshot;
+use std::io::stdout;
+
+/// Prints the input needed to receive exactly `amount` out of each WSOL/USDC pool (all at the
+/// same slot, as `simulate` quotes them), or out of `market` alone.
+pub fn quote_exact_out(
+    snapshot: &Snapshot,
+    direction: SwapDirection,
+    amount: f64,
+    market: Option<Pubkey>,
+    slot: Option<u64>,
+) -> eyre::Result<()> {
+    let (sim, markets) = Simulator::load_markets(snapshot, market, slot)?;
+
+    let mut wtr = WriterBuilder::new().from_writer(stdout());
+    for market in &markets {
+        match sim.quote_exact_out(market, direction, amount) {
+            Ok(r) => wtr.serialize(r)?,
+            Err(e) => tracing::warn!("Skipping {market}: {e:#}"),
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use eyre::{Result, WrapErr};
+use solfi_sim::constants::SOLFI_PROGRAM;
+use solfi_sim::types::{Snapshot, SolfiMarket};
+use std::fs;
+use std::path::Path;
+
+const ACCOUNTS_DIR: &str = "accounts";
+const PROGRAM_FILE: &str = "solfi.so";
+const ARGS_FILE: &str = "validator.args";
+const SCRIPT_FILE: &str = "start-validator.sh";
+
+/// Writes a snapshot out as a `solana-test-validator` fixture: one `--account` file per account,
+/// the program binary, an args file and a script that boots the validator from them.
+pub fn export_snapshot(snapshot: &Snapshot, out_dir: &Path) -> Result<()> {
+    let accounts = snapshot.read_accounts()?;
+    let accounts_dir = out_dir.join(ACCOUNTS_DIR);
+    fs::create_dir_all(&accounts_dir)?;
+
+    let program_path = snapshot.program_path();
+    fs::copy(&program_path, out_dir.join(PROGRAM_FILE))
+        .wrap_err_with(|| format!("failed to copy {}", program_path.display()))?;
+
+    let markets = accounts
+        .iter()
+        .filter_map(|a| SolfiMarket::from_account(&a.account).ok().map(|m| (a.address, m)))
+        .collect::<Vec<_>>();
+    // the validator has to start before the markets' cutoff, like the simulator does
+    let warp_slot = markets
+        .iter()
+        .map(|(_, m)| m.generated_slot)
+        .min()
+        .or_else(|| snapshot.metadata().map(|m| m.slot()));
+
+    let mut args =
+        vec!["--reset".to_string(), "--bpf-program".to_string(), SOLFI_PROGRAM.to_string(), PROGRAM_FILE.to_string()];
+    for account in &accounts {
+        let file = format!("{ACCOUNTS_DIR}/{}.json", account.address);
+        fs::write(out_dir.join(&file), serde_json::to_string_pretty(account)?)?;
+        args.extend(["--account".to_string(), account.address.to_string(), file]);
+    }
+    if let Some(slot) = warp_slot {
+        args.extend(["--warp-slot".to_string(), slot.to_string()]);
+    }
+    fs::write(out_dir.join(ARGS_FILE), args.join("\n") + "\n")?;
+
+    let mut script = format!(
+        "#!/usr/bin/env sh\n# Boots solana-test-validator with snapshot {}.\n",
+        snapshot.name()
+    );
+    if !markets.is_empty() {
+        script.push_str("# Markets stop quoting at their cutoff slot, so simulate soon after the validator is up:\n");
+        for (address, m) in &markets {
+            script.push_str(&format!(
+                "#   {address}: cutoff slot {}, {} slots after its generated slot\n",
+                m.cutoff_slot,
+                m.cutoff_slot.saturating_sub(m.generated_slot)
+            ));
+        }
+    }
+    script.push_str(&format!(
+        "set -e\n\
+         cd \"$(dirname \"$0\")\"\n\
+         exec solana-test-validator $(cat {ARGS_FILE}) \"$@\"\n"
+    ));
+    let script_path = out_dir.join(SCRIPT_FILE);
+    fs::write(&script_path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    tracing::info!(
+        "Exported {} accounts from snapshot {} to {}; run {}",
+        accounts.len(),
+        snapshot.name(),
+        out_dir.display(),
+        script_path.display()
+    );
+    Ok(())
+}
@@ -1,114 +1,245 @@
-use crate::constants::{SOLFI_MARKETS, USDC, WSOL};
-use crate::types::{AccountWithAddress, FetchMetadata};
-use eyre::{eyre, Result};
+use crate::cmd::rpc::PinnedClient;
+use eyre::{eyre, Result, WrapErr};
+use solana_account::Account;
+use solana_account::state_traits::StateMut;
+use solana_loader_v3_interface::get_program_data_address;
+use solana_loader_v3_interface::state::UpgradeableLoaderState;
 use solana_pubkey::Pubkey;
-use solana_rpc_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::commitment_config::CommitmentConfig;
+use solfi_sim::constants::{SOLFI_PROGRAM, USDC, WSOL};
+use solfi_sim::types::{
+    AccountWithAddress, FetchMetadata, FetchSource, MarketRegistry, Snapshot, SnapshotFormat,
+    SnapshotProgram, SolfiMarket,
+};
+use solfi_sim::utils::is_elf;
 use spl_associated_token_account::get_associated_token_address;
 use spl_token::state as token_state;
 use spl_token_2022::state as token2022_state;
 use solana_sdk::program_pack::Pack;
 
-pub async fn fetch_and_persist_accounts(rpc_url: String) -> Result<()> {
-    let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+pub async fn fetch_and_persist_accounts(
+    rpc_url: String,
+    label: Option<String>,
+    format: SnapshotFormat,
+) -> Result<()> {
+    let source = FetchSource { rpc_url: &rpc_url, commitment: "processed" };
+    let client = PinnedClient::new(rpc_url.clone(), CommitmentConfig::processed());
+    let registry = MarketRegistry::read()?.unwrap_or_default();
     let mut addresses: Vec<Pubkey> = vec![WSOL, USDC];
-    for market in SOLFI_MARKETS {
-        addresses.push(*market);
-        addresses.push(get_associated_token_address(market, &WSOL));
-        addresses.push(get_associated_token_address(market, &USDC));
+    for market in MarketRegistry::wsol_usdc_markets()? {
+        addresses.push(market);
+        match registry.find(&market) {
+            Some(entry) => {
+                addresses.push(entry.base_vault);
+                addresses.push(entry.quote_vault);
+            }
+            None => {
+                addresses.push(get_associated_token_address(&market, &WSOL));
+                addresses.push(get_associated_token_address(&market, &USDC));
+            }
+        }
     }
 
-    tracing::info!("Fetching {} accounts for canonical WSOL/USDC pools…", addresses.len());
+    tracing::info!("Fetching {} accounts for WSOL/USDC pools…", addresses.len());
 
-    let resp = client
-        .get_multiple_accounts_with_commitment(&addresses, CommitmentConfig::processed())
-        .await?;
-    let results = resp
-        .value
-        .iter()
+    let results = client
+        .get_multiple_accounts(&addresses)
+        .await?
+        .into_iter()
         .zip(addresses)
-        .filter_map(|(account, address)| Some(AccountWithAddress { address, account: account.clone()? }))
+        .filter_map(|(account, address)| Some(AccountWithAddress { address, account: account? }))
         .collect::<Vec<_>>();
 
-    for result in &results {
-        result.save_to_file()?;
-    }
-
-    let slot = resp.context.slot;
-    let metadata = FetchMetadata::new(slot);
-    metadata.save_to_file()?;
+    let metadata = snapshot_metadata(&client)?;
+    let slot = metadata.slot();
+    let program = fetch_program(&client).await;
+    let program = program.as_ref().map(|p| SnapshotProgram { elf: &p.elf, deploy_slot: Some(p.deploy_slot) });
+    let label = label.unwrap_or_else(|| "wsol-usdc".to_string());
+    let snapshot = Snapshot::save(&label, &results, &metadata, &source, format, program.as_ref())?;
 
-    tracing::info!("Fetched and saved {} accounts at slot {}", results.len(), slot);
+    tracing::info!(
+        "Fetched and saved {} accounts at slot {} to {}",
+        results.len(),
+        slot,
+        snapshot.dir().display()
+    );
     Ok(())
 }
 
 pub async fn fetch_and_persist_single_market(
     rpc_url: String,
     market: Pubkey,
-    quote_vault: Pubkey,
-    base_vault: Pubkey,
+    label: Option<String>,
+    format: SnapshotFormat,
 ) -> Result<()> {
-    let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+    let source = FetchSource { rpc_url: &rpc_url, commitment: "processed" };
+    let client = PinnedClient::new(rpc_url.clone(), CommitmentConfig::processed());
+
+    // only decoded for its vaults and mints; the market itself is saved from the batch below, which
+    // is pinned to at least this slot
+    let market_acc = client
+        .get_account(&market)
+        .await?
+        .ok_or_else(|| eyre!("market account {market} not found"))?;
+    let decoded = SolfiMarket::from_account(&market_acc)
+        .wrap_err_with(|| format!("{market} does not look like a SolFi market account"))?;
 
     tracing::info!(
         "Fetching single market {} with vaults (quote={}, base={})…",
-        market, quote_vault, base_vault
+        market, decoded.quote_vault, decoded.base_vault
     );
 
-    let quote_acc = client
-        .get_account_with_commitment(&quote_vault, CommitmentConfig::processed())
-        .await?
-        .value
-        .ok_or_else(|| eyre!("missing quote vault account"))?;
+    let to_fetch =
+        vec![market, decoded.quote_vault, decoded.base_vault, decoded.quote_mint, decoded.base_mint];
 
-    let base_acc = client
-        .get_account_with_commitment(&base_vault, CommitmentConfig::processed())
+    let results = client
+        .get_multiple_accounts(&to_fetch)
         .await?
-        .value
-        .ok_or_else(|| eyre!("missing base vault account"))?;
+        .into_iter()
+        .zip(to_fetch)
+        .map(|(account, address)| {
+            let account = account.ok_or_else(|| eyre!("missing account {address}"))?;
+            Ok(AccountWithAddress { address, account })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let quote_mint = vault_mint(&results[1].account)
+        .wrap_err_with(|| format!("invalid quote vault {}", decoded.quote_vault))?;
+    let base_mint = vault_mint(&results[2].account)
+        .wrap_err_with(|| format!("invalid base vault {}", decoded.base_vault))?;
+    eyre::ensure!(
+        quote_mint == decoded.quote_mint && base_mint == decoded.base_mint,
+        "vault mints (quote={quote_mint}, base={base_mint}) do not match the market's mints \
+         (quote={}, base={})",
+        decoded.quote_mint,
+        decoded.base_mint
+    );
 
-    let quote_mint = if quote_acc.owner == spl_token::id() {
-        token_state::Account::unpack(&quote_acc.data)?.mint
-    } else if quote_acc.owner == spl_token_2022::id() {
-        token2022_state::Account::unpack(&quote_acc.data)?.mint
-    } else {
-        return Err(eyre!("unsupported token program for quote vault"));
-    };
+    let metadata = snapshot_metadata(&client)?;
+    let slot = metadata.slot();
+    let program = fetch_program(&client).await;
+    let program = program.as_ref().map(|p| SnapshotProgram { elf: &p.elf, deploy_slot: Some(p.deploy_slot) });
+    let label = label.unwrap_or_else(|| market.to_string());
+    let snapshot = Snapshot::save(&label, &results, &metadata, &source, format, program.as_ref())?;
 
-    let base_mint = if base_acc.owner == spl_token::id() {
-        token_state::Account::unpack(&base_acc.data)?.mint
-    } else if base_acc.owner == spl_token_2022::id() {
-        token2022_state::Account::unpack(&base_acc.data)?.mint
-    } else {
-        return Err(eyre!("unsupported token program for base vault"));
-    };
+    tracing::info!(
+        "Fetched and saved single market {} (+vaults+mints) at slot {} ({} accounts) to {}",
+        market,
+        slot,
+        results.len(),
+        snapshot.dir().display()
+    );
+    Ok(())
+}
 
-    let to_fetch = vec![market, quote_vault, base_vault, quote_mint, base_mint];
+fn snapshot_metadata(client: &PinnedClient) -> Result<FetchMetadata> {
+    let metadata = client.metadata().ok_or_else(|| eyre!("no accounts were fetched"))?;
+    if !metadata.is_single_slot() {
+        tracing::warn!("Accounts were not all read at the same slot ({metadata})");
+    }
+    Ok(metadata)
+}
 
-    let resp = client
-        .get_multiple_accounts_with_commitment(&to_fetch, CommitmentConfig::processed())
-        .await?;
+pub(crate) struct DeployedProgram {
+    pub(crate) elf: Vec<u8>,
+    pub(crate) deploy_slot: u64,
+}
 
-    let results = resp
-        .value
-        .iter()
-        .zip(to_fetch)
-        .filter_map(|(account, address)| Some(AccountWithAddress { address, account: account.clone()? }))
-        .collect::<Vec<_>>();
+/// Downloads the SolFi program's programdata and strips its loader header. Failures only warn, as
+/// the snapshot can still fall back to `data/solfi.so`.
+pub(crate) async fn fetch_program(client: &PinnedClient) -> Option<DeployedProgram> {
+    let programdata_address = get_program_data_address(&SOLFI_PROGRAM);
+    let result = async {
+        let programdata = client
+            .get_account(&programdata_address)
+            .await?
+            .ok_or_else(|| eyre!("programdata account {programdata_address} not found"))?;
+        let UpgradeableLoaderState::ProgramData { slot, .. } = programdata.state()? else {
+            eyre::bail!("{programdata_address} is not a programdata account");
+        };
+        let elf = programdata
+            .data
+            .get(UpgradeableLoaderState::size_of_programdata_metadata()..)
+            .unwrap_or_default()
+            .to_vec();
+        eyre::ensure!(is_elf(&elf), "programdata {programdata_address} does not hold an ELF");
+        Ok(DeployedProgram { elf, deploy_slot: slot })
+    }
+    .await;
+
+    match result {
+        Ok(program) => {
+            tracing::info!(
+                "Fetched SolFi program ({} bytes) last deployed at slot {}",
+                program.elf.len(),
+                program.deploy_slot
+            );
+            Some(program)
+        }
+        Err(e) => {
+            tracing::warn!("Could not fetch the SolFi program binary: {e}");
+            None
+        }
+    }
+}
 
-    for result in &results {
-        result.save_to_file()?;
+fn vault_mint(vault: &Account) -> Result<Pubkey> {
+    if vault.owner == spl_token::id() {
+        Ok(token_state::Account::unpack(&vault.data)?.mint)
+    } else if vault.owner == spl_token_2022::id() {
+        Ok(token2022_state::Account::unpack(&vault.data)?.mint)
+    } else {
+        Err(eyre!("unsupported token program {}", vault.owner))
     }
+}
 
-    let slot = resp.context.slot;
-    let metadata = FetchMetadata::new(slot);
-    metadata.save_to_file()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::mock_rpc::{MockRpc, multiple_accounts, with_context};
+    use solana_sdk::bpf_loader_upgradeable;
+    use solfi_sim::constants::SOLFI_PROGRAM_PATH;
+
+    // the programdata account of a program deployed at `deploy_slot`, holding `elf`
+    fn programdata(deploy_slot: u64, elf: &[u8]) -> AccountWithAddress {
+        let header = UpgradeableLoaderState::size_of_programdata_metadata();
+        let mut account = Account {
+            lamports: 1,
+            data: vec![0; header + elf.len()],
+            owner: bpf_loader_upgradeable::id(),
+            ..Account::default()
+        };
+        account
+            .set_state(&UpgradeableLoaderState::ProgramData {
+                slot: deploy_slot,
+                upgrade_authority_address: Some(Pubkey::new_unique()),
+            })
+            .unwrap();
+        account.data[header..].copy_from_slice(elf);
+        AccountWithAddress { address: get_program_data_address(&SOLFI_PROGRAM), account }
+    }
 
-    tracing::info!(
-        "Fetched and saved single market {} (+vaults+mints) at slot {} ({} accounts)",
-        market,
-        slot,
-        results.len()
-    );
-    Ok(())
+    async fn fetch_from(account: AccountWithAddress) -> Option<DeployedProgram> {
+        let rpc = MockRpc::start(move |_, params| {
+            with_context(1, multiple_accounts(std::slice::from_ref(&account), params))
+        })
+        .await;
+        fetch_program(&PinnedClient::new(rpc.url(), CommitmentConfig::processed())).await
+    }
+
+    #[tokio::test]
+    async fn strips_the_loader_header_and_reads_the_deploy_slot() {
+        let elf = std::fs::read(SOLFI_PROGRAM_PATH).unwrap();
+        let program = fetch_from(programdata(350_000_000, &elf)).await.unwrap();
+        assert_eq!(program.deploy_slot, 350_000_000);
+        assert!(program.elf == elf);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_programdata_without_an_elf() {
+        assert!(fetch_from(programdata(1, b"not a program")).await.is_none());
+        let mut not_programdata = programdata(1, &[]);
+        not_programdata.account.set_state(&UpgradeableLoaderState::Uninitialized).unwrap();
+        assert!(fetch_from(not_programdata).await.is_none());
+    }
 }
@@ -0,0 +1,164 @@
+use eyre::{Result, WrapErr};
+use serde_json::Value;
+use solfi_sim::types::{
+    AccountWithAddress, FetchMetadata, FetchSource, MANIFEST_FILE_NAME, METADATA_FILE_NAME, Snapshot, SnapshotFormat,
+    SnapshotProgram, SolfiMarket,
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Turns a directory of `solana account --output json` dumps (or `solana-test-validator --account`
+/// files) into a snapshot. Without `slot`, the snapshot is stamped with the newest generated slot
+/// of the SolFi markets among the accounts.
+pub fn import_snapshot(
+    dir: &Path,
+    program: Option<&Path>,
+    label: Option<String>,
+    slot: Option<u64>,
+    format: SnapshotFormat,
+) -> Result<()> {
+    let accounts = read_dumps(dir)?;
+
+    let slot = match slot {
+        Some(slot) => slot,
+        None => accounts
+            .iter()
+            .filter_map(|a| SolfiMarket::from_account(&a.account).ok())
+            .map(|m| m.generated_slot)
+            .max()
+            .ok_or_else(|| eyre::eyre!("no SolFi market among the accounts to take a slot from; pass --slot"))?,
+    };
+
+    let program = program
+        .map(|path| fs::read(path).wrap_err_with(|| format!("failed to read {}", path.display())))
+        .transpose()?;
+
+    let label = label
+        .or_else(|| dir.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "import".to_string());
+    let snapshot = Snapshot::save(
+        &label,
+        &accounts,
+        &FetchMetadata::new(slot),
+        &FetchSource::IMPORTED,
+        format,
+        program.as_deref().map(|elf| SnapshotProgram { elf, deploy_slot: None }).as_ref(),
+    )?;
+
+    tracing::info!(
+        "Imported {} accounts{} at slot {} to {}",
+        accounts.len(),
+        if program.is_some() { " and the program" } else { "" },
+        slot,
+        snapshot.dir().display()
+    );
+    Ok(())
+}
+
+/// Reads every account dump in `dir`. A snapshot's metadata and manifest, and any other `.json`
+/// that is not an account, are skipped with a warning.
+fn read_dumps(dir: &Path) -> Result<Vec<AccountWithAddress>> {
+    let mut paths = fs::read_dir(dir)
+        .wrap_err_with(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+
+    let mut accounts = vec![];
+    for path in &paths {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if name == METADATA_FILE_NAME || name == MANIFEST_FILE_NAME {
+            tracing::warn!("Skipping {}: snapshot {name}, not an account", path.display());
+            continue;
+        }
+        match read_dump(path) {
+            Ok(account) => accounts.push(account),
+            Err(e) => tracing::warn!("Skipping {e:#}"),
+        }
+    }
+    eyre::ensure!(!accounts.is_empty(), "no account files (*.json) in {}", dir.display());
+
+    let mut seen = BTreeSet::new();
+    for account in &accounts {
+        eyre::ensure!(seen.insert(account.address), "account {} is in more than one file", account.address);
+    }
+    Ok(accounts)
+}
+
+/// Reads one dump. Files without a `pubkey` field take the address from their file name
+/// (`<address>.json` or `account_<address>.json`).
+fn read_dump(path: &Path) -> Result<AccountWithAddress> {
+    let content = fs::read_to_string(path).wrap_err_with(|| format!("failed to read {}", path.display()))?;
+    let mut value: Value =
+        serde_json::from_str(&content).wrap_err_with(|| format!("{} is not JSON", path.display()))?;
+    if let Value::Object(object) = &mut value {
+        if !object.contains_key("pubkey") && !object.contains_key("address") {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+            let address = stem.strip_prefix("account_").unwrap_or(stem);
+            object.insert("pubkey".to_string(), Value::String(address.to_string()));
+        }
+    }
+    serde_json::from_value(value).wrap_err_with(|| format!("{} is not an account dump", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::export_snapshot;
+    use crate::cmd::mock_rpc::data_account;
+    use serde_json::json;
+    use solana_pubkey::Pubkey;
+
+    const MARKET: &str = "DH4xmaWDnTzKXehVaPSNy9tMKJxnYL5Mo5U3oTHFtNYJ";
+
+    fn market() -> AccountWithAddress {
+        data_account(&Pubkey::from_str_const(MARKET))
+    }
+
+    #[test]
+    fn takes_the_address_from_the_file_name_and_skips_what_is_not_an_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let market = market();
+        // `solana account --output json` without the pubkey, as some tools write it
+        let account = serde_json::to_value(&market).unwrap()["account"].clone();
+        fs::write(dir.path().join(format!("{MARKET}.json")), json!({ "account": account }).to_string()).unwrap();
+        let other = AccountWithAddress { address: Pubkey::new_unique(), account: market.account.clone() };
+        let mut other_json = serde_json::to_value(&other).unwrap();
+        other_json.as_object_mut().unwrap().remove("pubkey");
+        fs::write(dir.path().join(format!("account_{}.json", other.address)), other_json.to_string()).unwrap();
+        // what a snapshot directory holds besides its accounts
+        FetchMetadata::new(7).save_to_file(dir.path()).unwrap();
+        fs::write(dir.path().join(MANIFEST_FILE_NAME), "{}").unwrap();
+        fs::write(dir.path().join("notes.json"), "[1, 2]").unwrap();
+
+        let mut accounts = read_dumps(dir.path()).unwrap();
+        accounts.sort_by_key(|a| a.address.to_string());
+        let mut expected = [market.address, other.address];
+        expected.sort_by_key(|a| a.to_string());
+        assert_eq!(accounts.iter().map(|a| a.address).collect::<Vec<_>>(), expected);
+        assert!(accounts.iter().all(|a| a.account == market.account));
+    }
+
+    #[test]
+    fn reads_validator_account_files() {
+        let snapshot_dir = tempfile::tempdir().unwrap();
+        let market = market();
+        market.save_to_file(snapshot_dir.path()).unwrap();
+        let out = tempfile::tempdir().unwrap();
+        export_snapshot(&Snapshot::open(snapshot_dir.path().to_str().unwrap()).unwrap(), out.path()).unwrap();
+
+        let accounts = read_dumps(&out.path().join("accounts")).unwrap();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].address, market.address);
+        assert_eq!(accounts[0].account, market.account);
+    }
+
+    #[test]
+    fn fails_without_any_account() {
+        let dir = tempfile::tempdir().unwrap();
+        FetchMetadata::new(7).save_to_file(dir.path()).unwrap();
+        assert!(read_dumps(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,74 @@
+use solana_pubkey::Pubkey;
+use solfi_sim::types::{MARKET_FIELDS, Snapshot, SolfiMarket};
+use std::ops::Range;
+
+const ROW_LEN: usize = 16;
+
+pub fn inspect_market(snapshot: &Snapshot, market: Pubkey) -> eyre::Result<()> {
+    let acct = snapshot.read_account(&market)?;
+    let data = acct.account.data.as_slice();
+
+    println!("== market {market} ==");
+    println!("owner:           {}", acct.account.owner);
+    println!("lamports:        {}", acct.account.lamports);
+    println!("data length:     {} bytes", data.len());
+
+    let decoded = SolfiMarket::from_account(&acct.account)?;
+    println!("{decoded}");
+
+    if let Some(metadata) = snapshot.metadata() {
+        let fetched = metadata.slot();
+        match fetched.checked_sub(decoded.generated_slot) {
+            Some(lag) => println!("staleness:       {lag} slots behind the fetch ({metadata})"),
+            None => println!(
+                "staleness:       {} slots ahead of the fetch ({metadata})",
+                decoded.generated_slot - fetched
+            ),
+        }
+    }
+
+    println!("\n== undecoded regions ==");
+    for range in undecoded_ranges(data.len()) {
+        println!("[{:#06x}..{:#06x}) {} bytes", range.start, range.end, range.len());
+        hexdump(&data[range.clone()], range.start);
+    }
+
+    Ok(())
+}
+
+fn undecoded_ranges(len: usize) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    for (_, field) in MARKET_FIELDS {
+        if field.start > start {
+            ranges.push(start..field.start);
+        }
+        start = start.max(field.end);
+    }
+    if start < len {
+        ranges.push(start..len);
+    }
+    ranges
+}
+
+// `hexdump -C` style, collapsing repeated all-zero rows into a single `*`
+fn hexdump(bytes: &[u8], base_offset: usize) {
+    let mut in_zero_run = false;
+    for (i, row) in bytes.chunks(ROW_LEN).enumerate() {
+        if row.iter().all(|b| *b == 0) && row.len() == ROW_LEN {
+            if !in_zero_run {
+                println!("  *");
+                in_zero_run = true;
+            }
+            continue;
+        }
+        in_zero_run = false;
+
+        let hex = row.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ");
+        let ascii = row
+            .iter()
+            .map(|b| if b.is_ascii_graphic() { *b as char } else { '.' })
+            .collect::<String>();
+        println!("  {:#06x}  {hex:<width$}  |{ascii}|", base_offset + i * ROW_LEN, width = ROW_LEN * 3 - 1);
+    }
+}
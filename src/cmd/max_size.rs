@@ -0,0 +1,38 @@
+use csv::WriterBuilder;
+use solana_pubkey::Pubkey;
+use solfi_sim::Simulator;
+use solfi_sim::limits::{PriceLimit, max_size};
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::Snapshot;
+use std::io::stdout;
+
+/// Prints the largest trade within `limit` for each WSOL/USDC pool and for all of them combined
+/// (all at the same slot), or for `market` alone.
+pub fn print_max_size(
+    snapshot: &Snapshot,
+    direction: SwapDirection,
+    limit: PriceLimit,
+    market: Option<Pubkey>,
+    probe: f64,
+    chunks: u32,
+    slot: Option<u64>,
+) -> eyre::Result<()> {
+    let (sim, markets) = Simulator::load_markets(snapshot, market, slot)?;
+
+    let mut targets = markets.iter().map(Some).collect::<Vec<_>>();
+    if market.is_none() {
+        targets.push(None);
+    }
+
+    let mut wtr = WriterBuilder::new().from_writer(stdout());
+    for target in targets {
+        let name = target.map_or_else(|| "the combined pools".to_string(), |m| m.to_string());
+        match max_size(&sim, target, direction, limit, probe, chunks) {
+            Ok(Some(r)) => wtr.serialize(r)?,
+            Ok(None) => tracing::warn!("No trade on {name} stays within the limit"),
+            Err(e) => tracing::warn!("Skipping {name}: {e:#}"),
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
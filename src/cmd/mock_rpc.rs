@@ -0,0 +1,130 @@
+//! A local JSON-RPC server answering with canned results, and fixtures, for testing the RPC commands.
+
+use serde_json::{Value, json};
+use solana_pubkey::Pubkey;
+use solfi_sim::types::AccountWithAddress;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+type Handler = dyn Fn(&str, &Value) -> Result<Value, Value> + Send + Sync;
+
+/// Answers every JSON-RPC request with `handler(method, params)` as its result until dropped.
+/// `getVersion`, which the client sends before filtered requests, is answered by the server.
+pub struct MockRpc {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl MockRpc {
+    pub async fn start(handler: impl Fn(&str, &Value) -> Value + Send + Sync + 'static) -> Self {
+        Self::start_fallible(move |method, params| Ok(handler(method, params))).await
+    }
+
+    /// Like [`MockRpc::start`], but the handler may answer with a JSON-RPC error object instead.
+    pub async fn start_fallible(
+        handler: impl Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handler: Arc<Handler> = Arc::new(handler);
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, handler.clone()));
+            }
+        });
+        Self { addr, task }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for MockRpc {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// An account as the RPC encodes it with base64 data.
+pub fn ui_account(account: &AccountWithAddress) -> Value {
+    serde_json::to_value(account).unwrap()["account"].clone()
+}
+
+/// An account from the `data/` snapshot.
+pub fn data_account(address: &Pubkey) -> AccountWithAddress {
+    AccountWithAddress::read_account(PathBuf::from(format!("data/account_{address}.json"))).unwrap()
+}
+
+/// The `getMultipleAccounts` value for the keys in `params`: each key's account among
+/// `accounts`, or null.
+pub fn multiple_accounts(accounts: &[AccountWithAddress], params: &Value) -> Value {
+    params[0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|key| {
+            accounts.iter().find(|a| a.address.to_string() == key.as_str().unwrap()).map_or(Value::Null, ui_account)
+        })
+        .collect()
+}
+
+/// A JSON-RPC error object.
+pub fn rpc_error(code: i64, message: &str) -> Value {
+    json!({ "code": code, "message": message })
+}
+
+/// A result wrapped in the response context of `slot`.
+pub fn with_context(slot: u64, value: Value) -> Value {
+    json!({ "context": { "slot": slot }, "value": value })
+}
+
+// one keep-alive connection: HTTP/1.1 requests with a content length, one after another
+async fn serve(stream: TcpStream, handler: Arc<Handler>) {
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        if stream.read_exact(&mut body).await.is_err() {
+            return;
+        }
+
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        let method = request["method"].as_str().unwrap_or_default();
+        let body = match method {
+            "getVersion" => Ok(json!({ "solana-core": "2.2.0", "feature-set": 0 })),
+            _ => handler(method, &request["params"]),
+        };
+        let body = match body {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": request["id"], "error": error }),
+        }
+        .to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        if stream.get_mut().write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
@@ -1,9 +1,38 @@
+mod backtest;
+mod book;
 mod cutoffs;
+mod diff;
+mod discover;
+mod exact_out;
+mod export;
 mod fetch;
+mod import;
+mod inspect;
+mod max_size;
+#[cfg(test)]
+mod mock_rpc;
+mod record;
+mod route;
+mod rpc;
+mod sequence;
 mod simulate;
 mod spreads;
+mod staleness;
 
+pub use backtest::backtest;
+pub use book::{book, BookFormat};
 pub use cutoffs::display_cutoffs;
+pub use diff::diff_market;
+pub use discover::discover_markets;
+pub use exact_out::quote_exact_out;
+pub use export::export_snapshot;
 pub use fetch::{fetch_and_persist_accounts, fetch_and_persist_single_market};
-pub use simulate::{simulate as simulate_all, simulate_generic_single_market};
-pub use spreads::run_spreads;
+pub use import::import_snapshot;
+pub use inspect::inspect_market;
+pub use max_size::print_max_size;
+pub use record::{record, RecordOptions};
+pub use route::route_order;
+pub use sequence::sequence;
+pub use simulate::simulate as simulate_all;
+pub use spreads::{run_spreads, SpreadMethod};
+pub use staleness::staleness;
@@ -0,0 +1,195 @@
+use crate::cmd::fetch::fetch_program;
+use crate::cmd::rpc::PinnedClient;
+use eyre::{eyre, Result, WrapErr};
+use solana_account::Account;
+use solana_pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solfi_sim::types::{AccountWithAddress, MarketRegistry, RecordedAccount, Recording, RecordingWriter, SolfiMarket};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+pub struct RecordOptions {
+    pub interval: Duration,
+    pub max_segment_bytes: u64,
+    pub max_total_bytes: u64,
+    /// Stop after this many polls instead of running until interrupted.
+    pub max_polls: Option<u64>,
+}
+
+/// Polls `markets` (default: the WSOL/USDC pools) with their vaults and mints, appending every
+/// account whose state changed to `recordings/<label>/`. Runs until Ctrl-C.
+pub async fn record(
+    rpc_url: String,
+    markets: Vec<Pubkey>,
+    label: Option<String>,
+    options: RecordOptions,
+) -> Result<()> {
+    let label = label.unwrap_or_else(|| match markets.first() {
+        Some(market) => market.to_string(),
+        None => "wsol-usdc".to_string(),
+    });
+    let markets = if markets.is_empty() { MarketRegistry::wsol_usdc_markets()? } else { markets };
+    record_into(rpc_url, &markets, Recording::new(&label), options).await
+}
+
+async fn record_into(
+    rpc_url: String,
+    markets: &[Pubkey],
+    recording: Recording,
+    options: RecordOptions,
+) -> Result<()> {
+    let client = PinnedClient::new(rpc_url, CommitmentConfig::processed());
+    let addresses = recorded_addresses(&client, markets).await?;
+
+    let mut writer = RecordingWriter::new(recording, options.max_segment_bytes, options.max_total_bytes)?;
+    if let Some(program) = fetch_program(&client).await {
+        writer.recording().save_program(&program.elf, program.deploy_slot)?;
+    }
+
+    tracing::info!(
+        "Recording {} accounts of {} markets to {} every {:?} (Ctrl-C to stop)",
+        addresses.len(),
+        markets.len(),
+        writer.recording().dir().display(),
+        options.interval
+    );
+
+    let mut last: HashMap<Pubkey, Account> = HashMap::new();
+    let mut ticker = tokio::time::interval(options.interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let (mut polls, mut written) = (0u64, 0u64);
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = ticker.tick() => {}
+        }
+
+        let accounts = match client.get_multiple_accounts(&addresses).await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                tracing::warn!("Poll failed: {e:#}");
+                continue;
+            }
+        };
+        let slot = client.slot().ok_or_else(|| eyre!("poll returned no context slot"))?;
+
+        // a fresh segment gets everything so it can be replayed without its predecessors
+        let full = writer.needs_new_segment();
+        let mut records = vec![];
+        for (address, account) in addresses.iter().zip(accounts) {
+            let Some(account) = account else {
+                continue;
+            };
+            if full || last.get(address) != Some(&account) {
+                last.insert(*address, account.clone());
+                records.push(RecordedAccount { slot, account: AccountWithAddress { address: *address, account } });
+            }
+        }
+        if !records.is_empty() {
+            writer.append(slot, &records)?;
+            written += records.len() as u64;
+            tracing::debug!("slot {slot}: recorded {} changed accounts", records.len());
+        }
+
+        polls += 1;
+        if options.max_polls.is_some_and(|max| polls >= max) {
+            break;
+        }
+    }
+
+    tracing::info!("Stopped after {polls} polls; recorded {written} account states");
+    Ok(())
+}
+
+/// The markets themselves plus the vaults and mints decoded from them.
+async fn recorded_addresses(client: &PinnedClient, markets: &[Pubkey]) -> Result<Vec<Pubkey>> {
+    let accounts = client.get_multiple_accounts(markets).await?;
+    let mut addresses = vec![];
+    for (market, account) in markets.iter().zip(accounts) {
+        let account = account.ok_or_else(|| eyre!("market account {market} not found"))?;
+        let decoded = SolfiMarket::from_account(&account)
+            .wrap_err_with(|| format!("{market} does not look like a SolFi market account"))?;
+        for address in [*market, decoded.quote_vault, decoded.base_vault, decoded.quote_mint, decoded.base_mint] {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::mock_rpc::{MockRpc, data_account, multiple_accounts, with_context};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const DH4X: &str = "DH4xmaWDnTzKXehVaPSNy9tMKJxnYL5Mo5U3oTHFtNYJ";
+
+    /// Serves the DH4x market, its vaults and mints from `data/`, one slot further on every
+    /// request. The market changes on every other request; nothing else ever does.
+    async fn mock_chain(market: Pubkey) -> MockRpc {
+        let decoded = SolfiMarket::from_account(&data_account(&market).account).unwrap();
+        let accounts = [market, decoded.quote_vault, decoded.base_vault, decoded.quote_mint, decoded.base_mint]
+            .map(|address| data_account(&address));
+        let accounts = Mutex::new(accounts.to_vec());
+        let slot = AtomicU64::new(100);
+        MockRpc::start(move |method, params| {
+            assert_eq!(method, "getMultipleAccounts");
+            let slot = slot.fetch_add(1, Ordering::SeqCst);
+            let mut accounts = accounts.lock().unwrap();
+            if slot.is_multiple_of(2) {
+                accounts[0].account.data[0] = accounts[0].account.data[0].wrapping_add(1);
+            }
+            with_context(slot, multiple_accounts(&accounts, params))
+        })
+        .await
+    }
+
+    fn options(max_segment_bytes: u64) -> RecordOptions {
+        RecordOptions {
+            interval: Duration::from_millis(1),
+            max_segment_bytes,
+            max_total_bytes: u64::MAX,
+            max_polls: Some(4),
+        }
+    }
+
+    #[tokio::test]
+    async fn records_full_state_then_only_changes() {
+        let market = Pubkey::from_str_const(DH4X);
+        let rpc = mock_chain(market).await;
+        let dir = tempfile::tempdir().unwrap();
+        let recording = Recording::open(dir.path().to_str().unwrap()).unwrap();
+
+        record_into(rpc.url(), &[market], recording, options(u64::MAX)).await.unwrap();
+
+        // slot 100 reads the markets and 101 looks for the program; polls run at 102..=105
+        let records = Recording::open(dir.path().to_str().unwrap()).unwrap().read_all().unwrap();
+        let (first, changes): (Vec<_>, Vec<_>) = records.iter().partition(|r| r.slot == 102);
+        assert_eq!(first.len(), 5);
+        assert_eq!(changes.iter().map(|r| r.slot).collect::<Vec<_>>(), [104]);
+        assert!(changes.iter().all(|r| r.account.address == market));
+    }
+
+    #[tokio::test]
+    async fn starts_every_segment_with_the_full_state() {
+        let market = Pubkey::from_str_const(DH4X);
+        let rpc = mock_chain(market).await;
+        let dir = tempfile::tempdir().unwrap();
+        let recording = Recording::open(dir.path().to_str().unwrap()).unwrap();
+
+        record_into(rpc.url(), &[market], recording, options(1)).await.unwrap();
+
+        let recording = Recording::open(dir.path().to_str().unwrap()).unwrap();
+        let segments = recording.segments().unwrap();
+        assert_eq!(segments.iter().map(|(slot, _)| *slot).collect::<Vec<_>>(), [102, 103, 104, 105]);
+        let records = recording.read_all().unwrap();
+        for (slot, _) in segments {
+            assert_eq!(records.iter().filter(|r| r.slot == slot).count(), 5);
+        }
+    }
+}
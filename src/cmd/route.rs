@@ -0,0 +1,39 @@
+use eyre::eyre;
+use solfi_sim::Simulator;
+use solfi_sim::route::route;
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::Snapshot;
+
+/// Prints how `amount` is best split across the WSOL/USDC pools, all quoted at the same slot,
+/// and what that gains over the best single pool.
+pub fn route_order(
+    snapshot: &Snapshot,
+    direction: SwapDirection,
+    amount: f64,
+    chunks: u32,
+    slot: Option<u64>,
+) -> eyre::Result<()> {
+    let (sim, _) = Simulator::load_markets(snapshot, None, slot)?;
+
+    let route = route(&sim, direction, amount, chunks)?
+        .ok_or_else(|| eyre!("the pools together cannot take {amount} in {chunks} chunks"))?;
+    println!("== {direction} {amount} across {} pools in {chunks} chunks ==\n", sim.markets().len());
+    for leg in &route.legs {
+        println!(
+            "{}  {:>6.2}%  {:>14.6} -> {:.6}",
+            leg.market,
+            leg.in_amount / amount * 100.0,
+            leg.in_amount,
+            leg.out_amount
+        );
+    }
+    println!("\nTotal out:        {:.6}", route.out_amount);
+    match (&route.best_single, route.improvement_bps()) {
+        (Some((market, out)), Some(bps)) => {
+            println!("Best single pool: {out:.6} ({market})");
+            println!("Improvement:      {:.6} ({bps:.2} bps)", route.out_amount - out);
+        }
+        _ => println!("No single pool takes the whole order"),
+    }
+    Ok(())
+}
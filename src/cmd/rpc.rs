@@ -0,0 +1,214 @@
+use eyre::Result;
+use solana_account::Account;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_pubkey::Pubkey;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_api::client_error::{Error as ClientError, ErrorKind};
+use solana_rpc_client_api::config::RpcAccountInfoConfig;
+use solana_rpc_client_api::custom_error::{
+    JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+    JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED,
+    JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+};
+use solana_rpc_client_api::request::RpcError;
+use solana_rpc_client_api::response::Response;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solfi_sim::types::FetchMetadata;
+use std::cell::Cell;
+use std::future::Future;
+use std::time::Duration;
+
+// getMultipleAccounts accepts at most 100 keys per request
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+const MAX_ATTEMPTS: u32 = 6;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// An RPC client that never reads older state than it has already seen: every request carries
+/// the newest context slot so far as `min_context_slot`, and transient errors or responses from a
+/// lagging node are retried with exponential backoff.
+pub struct PinnedClient {
+    client: RpcClient,
+    commitment: CommitmentConfig,
+    min_context_slot: Cell<Option<u64>>,
+    // context slots of the account batches, i.e. of the data a snapshot is built from
+    slot_range: Cell<Option<(u64, u64)>>,
+}
+
+impl PinnedClient {
+    pub fn new(rpc_url: String, commitment: CommitmentConfig) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(rpc_url, commitment),
+            commitment,
+            min_context_slot: Cell::new(None),
+            slot_range: Cell::new(None),
+        }
+    }
+
+    /// Fetches one account. Its slot pins later requests but is not part of the slot range.
+    pub async fn get_account(&self, address: &Pubkey) -> Result<Option<Account>> {
+        // getAccountInfo's client wrapper flattens errors into strings, which hides transient ones
+        let what = format!("getMultipleAccounts ({address})");
+        let keys = [*address];
+        let (_, accounts) =
+            self.pinned(&what, |config| self.client.get_multiple_accounts_with_config(&keys, config)).await?;
+        Ok(accounts.into_iter().next().flatten())
+    }
+
+    /// Fetches accounts in batches of [`MAX_MULTIPLE_ACCOUNTS`], recording each batch's slot in
+    /// the slot range.
+    pub async fn get_multiple_accounts(&self, addresses: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let mut accounts = Vec::with_capacity(addresses.len());
+        for chunk in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let what = format!("getMultipleAccounts ({} keys)", chunk.len());
+            let (slot, batch) =
+                self.pinned(&what, |config| self.client.get_multiple_accounts_with_config(chunk, config)).await?;
+            let (lower, upper) = self.slot_range.get().unwrap_or((slot, slot));
+            self.slot_range.set(Some((lower.min(slot), upper.max(slot))));
+            accounts.extend(batch);
+        }
+        Ok(accounts)
+    }
+
+    /// The newest context slot seen so far.
+    pub fn slot(&self) -> Option<u64> {
+        self.min_context_slot.get()
+    }
+
+    /// Fetch metadata covering every batch fetched so far.
+    pub fn metadata(&self) -> Option<FetchMetadata> {
+        self.slot_range.get().map(|(lower, upper)| FetchMetadata::with_range(lower, upper))
+    }
+
+    async fn pinned<T, F, Fut>(&self, what: &str, request: F) -> Result<(u64, T)>
+    where
+        F: Fn(RpcAccountInfoConfig) -> Fut,
+        Fut: Future<Output = Result<Response<T>, ClientError>>,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            let min_context_slot = self.min_context_slot.get();
+            let config = RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(self.commitment),
+                min_context_slot,
+                ..Default::default()
+            };
+            let failure = match request(config).await {
+                Ok(resp) if min_context_slot.is_none_or(|min| resp.context.slot >= min) => {
+                    self.min_context_slot.set(Some(resp.context.slot));
+                    return Ok((resp.context.slot, resp.value));
+                }
+                Ok(resp) => format!(
+                    "answered at slot {}, behind the pinned slot {}",
+                    resp.context.slot,
+                    min_context_slot.unwrap_or_default()
+                ),
+                Err(err) if is_transient(&err) => err.to_string(),
+                Err(err) => return Err(err.into()),
+            };
+
+            eyre::ensure!(attempt < MAX_ATTEMPTS, "{what} failed after {attempt} attempts: {failure}");
+            tracing::warn!("{what}: {failure}; retrying in {backoff:?}");
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+fn is_transient(err: &ClientError) -> bool {
+    match err.kind() {
+        ErrorKind::Io(_) | ErrorKind::Reqwest(_) => true,
+        ErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => matches!(
+            *code,
+            JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED
+                | JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+                | JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cmd::mock_rpc::{MockRpc, rpc_error, with_context};
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    // answers getMultipleAccounts with null accounts at each of `slots` in turn, where `None` is
+    // a transient error, and records the minContextSlot of every request
+    async fn mock(slots: Vec<Option<u64>>) -> (MockRpc, Arc<Mutex<Vec<Option<u64>>>>) {
+        let pins = Arc::new(Mutex::new(vec![]));
+        let seen = pins.clone();
+        let slots = Mutex::new(slots.into_iter());
+        let rpc = MockRpc::start_fallible(move |method, params| {
+            assert_eq!(method, "getMultipleAccounts");
+            seen.lock().unwrap().push(params[1]["minContextSlot"].as_u64());
+            let keys = params[0].as_array().unwrap().len();
+            match slots.lock().unwrap().next().expect("no more answers") {
+                Some(slot) => Ok(with_context(slot, Value::Array(vec![Value::Null; keys]))),
+                None => Err(rpc_error(JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY, "Node is unhealthy")),
+            }
+        })
+        .await;
+        (rpc, pins)
+    }
+
+    fn client(rpc: &MockRpc) -> PinnedClient {
+        PinnedClient::new(rpc.url(), CommitmentConfig::confirmed())
+    }
+
+    #[tokio::test]
+    async fn retries_a_transient_error() {
+        let (rpc, pins) = mock(vec![None, Some(10)]).await;
+        let client = client(&rpc);
+
+        let accounts = client.get_multiple_accounts(&[Pubkey::new_unique()]).await.unwrap();
+        assert_eq!(accounts, vec![None]);
+        assert_eq!(*pins.lock().unwrap(), [None, None]);
+        assert_eq!(client.slot(), Some(10));
+    }
+
+    #[tokio::test]
+    async fn pins_later_requests_and_retries_a_slot_that_goes_backwards() {
+        let (rpc, pins) = mock(vec![Some(10), Some(9), Some(12)]).await;
+        let client = client(&rpc);
+
+        client.get_account(&Pubkey::new_unique()).await.unwrap();
+        client.get_multiple_accounts(&[Pubkey::new_unique()]).await.unwrap();
+        assert_eq!(*pins.lock().unwrap(), [None, Some(10), Some(10)]);
+        assert_eq!(client.slot(), Some(12));
+        // the single account pins, but only batches make up the snapshot's slots
+        assert_eq!(client.metadata().unwrap().slot(), 12);
+        assert!(client.metadata().unwrap().is_single_slot());
+    }
+
+    #[tokio::test]
+    async fn fails_at_once_on_other_errors() {
+        let calls = Arc::new(Mutex::new(0));
+        let counted = calls.clone();
+        let rpc = MockRpc::start_fallible(move |_, _| {
+            *counted.lock().unwrap() += 1;
+            Err(rpc_error(-32602, "Invalid params"))
+        })
+        .await;
+
+        assert!(client(&rpc).get_multiple_accounts(&[Pubkey::new_unique()]).await.is_err());
+        assert_eq!(*calls.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn detects_batches_read_at_different_slots() {
+        let keys = (0..MAX_MULTIPLE_ACCOUNTS + 1).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let (rpc, _) = mock(vec![Some(20), Some(21)]).await;
+        let client = client(&rpc);
+
+        assert_eq!(client.get_multiple_accounts(&keys).await.unwrap().len(), keys.len());
+        let metadata = client.metadata().unwrap();
+        assert!(!metadata.is_single_slot());
+        assert_eq!(metadata.to_string(), "fetched between slots 20 and 21");
+    }
+}
@@ -0,0 +1,104 @@
+use clap::ValueEnum;
+use csv::Writer;
+use eyre::{WrapErr, eyre};
+use solana_pubkey::Pubkey;
+use solfi_sim::Simulator;
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::Snapshot;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Write, stdout};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(serde::Serialize)]
+struct StepRow {
+    step: usize,
+    market: String,
+    direction: String,
+    in_amount: f64,
+    out_amount: Option<f64>,
+    /// Quote tokens per base token.
+    price: Option<f64>,
+    /// Price change since the first step with the same market and direction.
+    drift_bps: Option<f64>,
+    base_vault_balance: f64,
+    quote_vault_balance: f64,
+    error: Option<String>,
+    error_name: Option<String>,
+}
+
+/// Applies `steps` (`direction:amount[:market]`, `repeat` times over) one after another to the
+/// same state, so each swap sees the pools as the previous ones left them. Steps without a market
+/// use `market`.
+pub fn sequence(
+    snapshot: &Snapshot,
+    steps: &[String],
+    market: Option<Pubkey>,
+    repeat: u32,
+    csv: Option<&Path>,
+) -> eyre::Result<()> {
+    let steps = steps.iter().map(|s| parse_step(s, market)).collect::<eyre::Result<Vec<_>>>()?;
+    eyre::ensure!(!steps.is_empty(), "no swaps to run");
+
+    let sim = Simulator::load_snapshot(snapshot)?;
+    let mut markets = steps.iter().map(|(_, _, m)| *m).collect::<Vec<_>>();
+    markets.sort();
+    markets.dedup();
+    let mut session = sim.session(&markets);
+
+    let out: Box<dyn Write> = match csv {
+        Some(path) => Box::new(fs::File::create(path)?),
+        None => Box::new(stdout()),
+    };
+    let mut w = Writer::from_writer(out);
+
+    let mut first_price = HashMap::new();
+    let steps = steps.iter().cycle().take(steps.len() * repeat as usize);
+    for (i, (direction, amount, market)) in steps.enumerate() {
+        let r = session.swap(market, *direction, *amount)?;
+        let price = r.average_price(*direction);
+        let drift_bps = price.map(|price| {
+            let first = *first_price.entry((*market, *direction)).or_insert(price);
+            (price - first) / first * 10_000.0
+        });
+        let (base_vault_balance, quote_vault_balance) = session.vault_balances(market)?;
+        w.serialize(StepRow {
+            step: i + 1,
+            market: r.market,
+            direction: direction.to_string(),
+            in_amount: r.in_amount,
+            out_amount: r.out_amount,
+            price,
+            drift_bps,
+            base_vault_balance,
+            quote_vault_balance,
+            error: r.error,
+            error_name: r.error_name,
+        })?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+fn parse_step(
+    step: &str,
+    default_market: Option<Pubkey>,
+) -> eyre::Result<(SwapDirection, f64, Pubkey)> {
+    let mut parts = step.split(':');
+    let (Some(direction), Some(amount)) = (parts.next(), parts.next()) else {
+        eyre::bail!("swap {step} is not direction:amount[:market]");
+    };
+    let direction =
+        SwapDirection::from_str(direction, true).map_err(|e| eyre!("swap {step}: {e}"))?;
+    let amount = amount.parse::<f64>().wrap_err_with(|| format!("swap {step}: invalid amount"))?;
+    let market = match parts.next() {
+        Some(market) => {
+            Pubkey::from_str(market).wrap_err_with(|| format!("swap {step}: invalid market"))?
+        }
+        None => default_market
+            .ok_or_else(|| eyre!("swap {step} has no market and --market is not set"))?,
+    };
+    eyre::ensure!(parts.next().is_none(), "swap {step} is not direction:amount[:market]");
+    Ok((direction, amount, market))
+}
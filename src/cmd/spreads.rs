@@ -1,53 +1,87 @@
-use crate::cmd::{display_cutoffs, simulate_all, simulate_generic_single_market};
-use crate::swap::SwapDirection;
-use crate::types::AccountWithAddress;
-use crate::utils::u64_at_offset;
+use crate::cmd::display_cutoffs;
 use csv::WriterBuilder;
 use eyre::WrapErr;
 use solana_pubkey::Pubkey;
+use solfi_sim::Simulator;
+use solfi_sim::limits::mid_price;
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::Snapshot;
 use std::collections::HashMap;
 use std::path::Path;
 use std::str::FromStr;
 
-const GEN_OFFSET: usize = 464;
+/// How the bid side of a spread is measured.
+#[derive(clap::ValueEnum, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpreadMethod {
+    /// Sell back exactly the base the buy leg received.
+    RoundTrip,
+    /// Sell the base the USDC amount is worth at mid, so both legs have the same notional.
+    Independent,
+}
+
+impl SpreadMethod {
+    fn label(self) -> &'static str {
+        match self {
+            SpreadMethod::RoundTrip => "round trip",
+            SpreadMethod::Independent => "independent legs",
+        }
+    }
+}
 
 #[derive(serde::Serialize, Debug, Clone)]
-struct SpreadRow {
-    amount_usdc: f64,
-    market: String,
-    buy_price: f64,
-    sell_price: f64,
-    spread_usd: f64,
-    spread_bps: f64,
+pub(crate) struct SpreadRow {
+    pub(crate) amount_usdc: f64,
+    pub(crate) market: String,
+    pub(crate) method: SpreadMethod,
+    pub(crate) buy_price: f64,
+    pub(crate) sell_price: f64,
+    pub(crate) spread_usd: f64,
+    pub(crate) spread_bps: f64,
+    pub(crate) buy_compute_units: u64,
+    pub(crate) sell_compute_units: u64,
 }
 
+/// Prints or writes the spread of each size on each pool, or on `market` alone, by every method in
+/// `methods`. Printed, the methods are columns side by side; the CSV has one row per method, told
+/// apart by its `method` column.
+#[allow(clippy::too_many_arguments)]
 pub fn run_spreads(
+    snapshot: &Snapshot,
     starting_usdc: f64,
     sizes: Option<Vec<f64>>,
     csv: Option<&Path>,
     market: Option<&str>,
-    market_token_quote: Option<&str>,
-    market_token_base: Option<&str>,
     slot_opt: Option<u64>,
+    methods: &[SpreadMethod],
+    probe: f64,
 ) -> eyre::Result<()> {
     let sweep = sizes.unwrap_or_else(|| vec![starting_usdc]);
+    // built once and shared by every leg and size of the sweep
+    let sim = Simulator::load_snapshot(snapshot)?.with_slot(slot_opt);
 
-    if let (Some(mkt), Some(quote), Some(base)) = (market, market_token_quote, market_token_base) {
+    if let Some(mkt) = market {
+        let market_pk =
+            Pubkey::from_str(mkt).wrap_err_with(|| format!("invalid market pubkey: {mkt}"))?;
+        let mids = mids(&sim, &[market_pk], methods, probe)?;
+        let mid = mids.get(&market_pk).copied();
         if let Some(csv_path) = csv {
             let mut w = WriterBuilder::new().has_headers(true).from_path(csv_path)?;
             for amt in &sweep {
-                if let Some(row) = compute_single_market_row(*amt, mkt, quote, base, slot_opt)? {
-                    w.serialize(&row)?;
+                for method in methods {
+                    if let Some(row) = compute_single_market_row(&sim, *amt, mkt, *method, mid)? {
+                        w.serialize(&row)?;
+                    }
                 }
             }
             w.flush()?;
         } else {
-            if let Some(gen) = read_generated_slot_for_market(mkt)? {
+            if let Some(gen) = sim.generated_slot(&market_pk) {
                 println!("== using market snapshot generated slot {gen} ==\n");
             }
             let multi = sweep.len() > 1;
             for amt in &sweep {
-                print_single_market_one_size(*amt, mkt, quote, base, slot_opt)?;
+                print_single_market_one_size(&sim, *amt, mkt, methods, mid)?;
                 if multi {
                     println!();
                 }
@@ -56,92 +90,110 @@ pub fn run_spreads(
         return Ok(());
     }
 
+    // both legs of every pool are simulated at the same slot
+    let pool_slot = sim.slot_for(sim.markets());
+    let sim = sim.with_slot(pool_slot);
+    let mids = mids(&sim, sim.markets(), methods, probe)?;
+
     if let Some(csv_path) = csv {
         let mut w = WriterBuilder::new().has_headers(true).from_path(csv_path)?;
         for amt in &sweep {
-            let rows = compute_multi_pool_rows(*amt)?;
-            for r in rows {
-                w.serialize(&r)?;
+            for method in methods {
+                let rows = compute_multi_pool_rows(&sim, *amt, *method, &mids)?;
+                for r in rows {
+                    w.serialize(&r)?;
+                }
             }
         }
         w.flush()?;
     } else {
-        display_cutoffs();
+        display_cutoffs(snapshot)?;
         for (i, amt) in sweep.iter().enumerate() {
-            if i == 0 {
+            if i == 0 && methods == [SpreadMethod::RoundTrip] {
                 println!("\nCalculating spreads based on a round trip starting with {:.2} USDC...\n", amt);
             } else {
                 println!("\n== Amount: {:.2} USDC ==\n", amt);
             }
-            print_multi_pool_one_size(*amt)?;
+            print_multi_pool_one_size(&sim, *amt, methods, &mids)?;
         }
     }
 
     Ok(())
 }
 
+// each market's mid, found once per run and only when a method sizes its sell leg by it
+fn mids(
+    sim: &Simulator,
+    markets: &[Pubkey],
+    methods: &[SpreadMethod],
+    probe: f64,
+) -> eyre::Result<HashMap<Pubkey, f64>> {
+    let mut mids = HashMap::new();
+    if methods.contains(&SpreadMethod::Independent) {
+        eyre::ensure!(probe > 0.0, "probe size must be positive");
+        for market in markets {
+            match mid_price(sim, Some(market), probe)? {
+                Some(mid) => {
+                    mids.insert(*market, mid);
+                }
+                None => tracing::warn!("Could not find a mid price on {market}"),
+            }
+        }
+    }
+    Ok(mids)
+}
+
 fn print_single_market_one_size(
+    sim: &Simulator,
     usdc_amount_in: f64,
     market: &str,
-    quote_vault: &str,
-    base_vault: &str,
-    slot_opt: Option<u64>,
+    methods: &[SpreadMethod],
+    mid: Option<f64>,
 ) -> eyre::Result<()> {
-    let Some(row) = compute_single_market_row(usdc_amount_in, market, quote_vault, base_vault, slot_opt)? else {
+    let mut rows = vec![];
+    for method in methods {
+        rows.extend(compute_single_market_row(sim, usdc_amount_in, market, *method, mid)?);
+    }
+    if rows.is_empty() {
         return Ok(());
-    };
+    }
 
-    println!("Calculating single-market spread (round trip) with {:.2} USDC on {}...\n",
-             row.amount_usdc, market);
-    println!("--- Market: {} ---", row.market);
-    println!("  Buy BASE at:  ${:<10.6} (Ask)", row.buy_price);
-    println!("  Sell BASE at: ${:<10.6} (Bid)",  row.sell_price);
-    println!("  Spread:       ${:<10.6}",        row.spread_usd);
-    println!("  Spread:       {:<10.2} bps",     row.spread_bps);
+    let method_names = methods.iter().map(|m| m.label()).collect::<Vec<_>>().join(" and ");
+    println!("Calculating single-market spread ({method_names}) with {:.2} USDC on {}...\n",
+             usdc_amount_in, market);
+    println!("--- Market: {market} ---");
+    print_side_by_side(&rows, methods, "BASE", 6);
     Ok(())
 }
 
-fn compute_single_market_row(
+/// The spread of `usdc_amount_in` on `market` by `method`; `mid` is the market's mid, which the
+/// independent method sizes its sell leg at. `None` when a leg fails or there is no mid.
+pub(crate) fn compute_single_market_row(
+    sim: &Simulator,
     usdc_amount_in: f64,
     market: &str,
-    quote_vault: &str,
-    base_vault: &str,
-    slot_opt: Option<u64>,
+    method: SpreadMethod,
+    mid: Option<f64>,
 ) -> eyre::Result<Option<SpreadRow>> {
     let market_pk = Pubkey::from_str(market)?;
-    let quote_vault_pk = Pubkey::from_str(quote_vault)?;
-    let base_vault_pk  = Pubkey::from_str(base_vault)?;
-
-    let buy = simulate_generic_single_market(
-        market_pk,
-        quote_vault_pk,
-        base_vault_pk,
-        usdc_amount_in,
-        SwapDirection::UsdcToSol,
-        slot_opt,
-        false,
-    )?;
+
+    let buy = sim.quote_market(&market_pk, SwapDirection::UsdcToSol, usdc_amount_in)?;
     let Some(base_out) = buy.out_amount else {
-        println!("Buy leg failed: {:?}", buy.error);
+        tracing::warn!("Buy leg failed on {market}: {:?} ({:?})", buy.error, buy.error_name);
         return Ok(None);
     };
 
-    let sell = simulate_generic_single_market(
-        market_pk,
-        quote_vault_pk,
-        base_vault_pk,
-        base_out,
-        SwapDirection::SolToUsdc,
-        slot_opt,
-        false,
-    )?;
+    let Some(base_sold) = sell_size(method, usdc_amount_in, base_out, mid) else {
+        return Ok(None);
+    };
+    let sell = sim.quote_market(&market_pk, SwapDirection::SolToUsdc, base_sold)?;
     let Some(usdc_out_final) = sell.out_amount else {
-        println!("Sell leg failed: {:?}", sell.error);
+        tracing::warn!("Sell leg failed on {market}: {:?} ({:?})", sell.error, sell.error_name);
         return Ok(None);
     };
 
     let buy_price  = usdc_amount_in / base_out;
-    let sell_price = usdc_out_final / base_out;
+    let sell_price = usdc_out_final / base_sold;
     if !buy_price.is_finite() || !sell_price.is_finite() || buy_price <= 0.0 || sell_price <= 0.0 {
         return Ok(None);
     }
@@ -154,92 +206,125 @@ fn compute_single_market_row(
     Ok(Some(SpreadRow {
         amount_usdc: usdc_amount_in,
         market: market.to_string(),
+        method,
         buy_price,
         sell_price,
         spread_usd: spread_usdc,
         spread_bps,
+        buy_compute_units: buy.compute_units,
+        sell_compute_units: sell.compute_units,
     }))
 }
 
-fn print_multi_pool_one_size(usdc_amount_in: f64) -> eyre::Result<()> {
-    let mut rows = compute_multi_pool_rows(usdc_amount_in)?;
-    if rows.is_empty() {
+fn print_multi_pool_one_size(
+    sim: &Simulator,
+    usdc_amount_in: f64,
+    methods: &[SpreadMethod],
+    mids: &HashMap<Pubkey, f64>,
+) -> eyre::Result<()> {
+    // every method's rows for a market together, in the order of the first method that has one
+    let mut markets: Vec<Vec<SpreadRow>> = vec![];
+    for method in methods {
+        for row in compute_multi_pool_rows(sim, usdc_amount_in, *method, mids)? {
+            match markets.iter_mut().find(|rows| rows[0].market == row.market) {
+                Some(rows) => rows.push(row),
+                None => markets.push(vec![row]),
+            }
+        }
+    }
+    if markets.is_empty() {
         println!("Could not complete a round-trip simulation on any market.");
         return Ok(());
     }
-    rows.sort_by(|a, b| a.spread_bps.partial_cmp(&b.spread_bps).unwrap());
-    for r in rows {
-        println!("--- Market: {} ---", r.market);
-        println!("  Buy SOL at:  ${:<10.4} (Ask)", r.buy_price);
-        println!("  Sell SOL at: ${:<10.4} (Bid)", r.sell_price);
-        println!("  Spread:      ${:<10.6}",       r.spread_usd);
-        println!("  Spread:      {:<10.2} bps\n",  r.spread_bps);
+    markets.sort_by(|a, b| a[0].spread_bps.partial_cmp(&b[0].spread_bps).unwrap());
+    for rows in markets {
+        println!("--- Market: {} ---", rows[0].market);
+        print_side_by_side(&rows, methods, "SOL", 4);
+        println!();
     }
     Ok(())
 }
 
-fn compute_multi_pool_rows(usdc_amount_in: f64) -> eyre::Result<Vec<SpreadRow>> {
-    let buy_side_results =
-        simulate_all(SwapDirection::UsdcToSol, Some(usdc_amount_in), None, true, false)?;
-
-    let sol_outputs_by_market: HashMap<String, f64> = buy_side_results
-        .into_iter()
-        .filter_map(|r| r.out_amount.map(|sol_out| (r.market, sol_out)))
-        .collect();
-
-    if sol_outputs_by_market.is_empty() {
-        return Ok(Vec::new());
+// one column per method, headed by its name when there is more than one
+fn print_side_by_side(rows: &[SpreadRow], methods: &[SpreadMethod], base: &str, decimals: usize) {
+    let line = |label: &str, cell: &dyn Fn(&SpreadRow) -> String, suffix: &str| {
+        let mut out = format!("  {label:<14}");
+        for method in methods {
+            let value = rows.iter().find(|r| r.method == *method).map_or("-".to_string(), cell);
+            out.push_str(&format!("{value:<24}"));
+        }
+        println!("{}", format!("{out}{suffix}").trim_end());
+    };
+    if methods.len() > 1 {
+        line("", &|r| r.method.label().to_string(), "");
     }
+    line(&format!("Buy {base} at:"), &|r| format!("${:.decimals$}", r.buy_price), "(Ask)");
+    line(&format!("Sell {base} at:"), &|r| format!("${:.decimals$}", r.sell_price), "(Bid)");
+    line("Spread:", &|r| format!("${:.6}", r.spread_usd), "");
+    line("Spread:", &|r| format!("{:.2} bps", r.spread_bps), "");
+    line("Compute:", &|r| format!("{} / {} CU", r.buy_compute_units, r.sell_compute_units), "(buy / sell)");
+}
+
+fn compute_multi_pool_rows(
+    sim: &Simulator,
+    usdc_amount_in: f64,
+    method: SpreadMethod,
+    mids: &HashMap<Pubkey, f64>,
+) -> eyre::Result<Vec<SpreadRow>> {
+    let buy_side_results = sim.quote_all(SwapDirection::UsdcToSol, usdc_amount_in)?;
 
     let mut rows = Vec::new();
 
-    for (market, sol_out) in sol_outputs_by_market {
+    for (market, buy) in sim.markets().iter().zip(buy_side_results) {
+        let Some(sol_out) = buy.out_amount else {
+            continue;
+        };
         if sol_out <= 0.0 {
             continue;
         }
 
-        if let Ok(sell_results) =
-            simulate_all(SwapDirection::SolToUsdc, Some(sol_out), None, true, false)
-        {
-            if let Some(sell_result) = sell_results.into_iter().find(|r| r.market == market) {
-                if let Some(usdc_out_final) = sell_result.out_amount {
-                    let buy_price = usdc_amount_in / sol_out;
-                    let sell_price = usdc_out_final / sol_out;
-
-                    if buy_price > 0.0 && sell_price > 0.0 {
-                        let spread_usdc = buy_price - sell_price;
-                        let mid_price = (buy_price + sell_price) / 2.0;
-                        if mid_price <= 0.0 {
-                            continue;
-                        }
-                        let spread_bps = (spread_usdc / mid_price) * 10_000.0;
-
-                        rows.push(SpreadRow {
-                            amount_usdc: usdc_amount_in,
-                            market: market.clone(),
-                            buy_price,
-                            sell_price,
-                            spread_usd: spread_usdc,
-                            spread_bps,
-                        });
-                    }
-                }
+        let Some(sol_sold) = sell_size(method, usdc_amount_in, sol_out, mids.get(market).copied())
+        else {
+            continue;
+        };
+        let sell = sim.quote(market, SwapDirection::SolToUsdc, sol_sold)?;
+        let Some(usdc_out_final) = sell.out_amount else {
+            continue;
+        };
+
+        let buy_price = usdc_amount_in / sol_out;
+        let sell_price = usdc_out_final / sol_sold;
+
+        if buy_price > 0.0 && sell_price > 0.0 {
+            let spread_usdc = buy_price - sell_price;
+            let mid_price = (buy_price + sell_price) / 2.0;
+            if mid_price <= 0.0 {
+                continue;
             }
+            let spread_bps = (spread_usdc / mid_price) * 10_000.0;
+
+            rows.push(SpreadRow {
+                amount_usdc: usdc_amount_in,
+                market: market.to_string(),
+                method,
+                buy_price,
+                sell_price,
+                spread_usd: spread_usdc,
+                spread_bps,
+                buy_compute_units: buy.compute_units,
+                sell_compute_units: sell.compute_units,
+            });
         }
     }
 
     Ok(rows)
 }
 
-fn read_generated_slot_for_market(market: &str) -> eyre::Result<Option<u64>> {
-    let market_pk = Pubkey::from_str(market)
-        .wrap_err_with(|| format!("invalid market pubkey: {market}"))?;
-    match AccountWithAddress::read_account(format!("data/account_{market_pk}.json").into()) {
-        Ok(acct) => {
-            let gen = u64_at_offset(acct.account.data.as_slice(), GEN_OFFSET)
-                .wrap_err("failed to read generated slot")?;
-            Ok(Some(gen))
-        }
-        Err(_) => Ok(None),
+/// Base sold on the sell leg: what the buy leg got for a round trip, else the base
+/// `usdc_amount_in` buys at `mid`. `None` when the market has no mid to size by.
+fn sell_size(method: SpreadMethod, usdc_amount_in: f64, base_bought: f64, mid: Option<f64>) -> Option<f64> {
+    match method {
+        SpreadMethod::RoundTrip => Some(base_bought),
+        SpreadMethod::Independent => mid.map(|mid| usdc_amount_in / mid),
     }
 }
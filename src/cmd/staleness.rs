@@ -0,0 +1,103 @@
+use crate::cmd::simulate::{DEFAULT_SWAP_AMOUNT_SOL, DEFAULT_SWAP_AMOUNT_USDC};
+use csv::WriterBuilder;
+use solana_pubkey::Pubkey;
+use solfi_sim::Simulator;
+use solfi_sim::swap::SwapDirection;
+use solfi_sim::types::Snapshot;
+use std::path::Path;
+
+#[derive(serde::Serialize)]
+struct StalenessRow {
+    market: String,
+    slot: u64,
+    slots_since_generated: u64,
+    generated_slot: u64,
+    cutoff_slot: u64,
+    in_amount: f64,
+    out_amount: Option<f64>,
+    error: Option<String>,
+    error_name: Option<String>,
+    compute_units: u64,
+}
+
+/// Repeats one swap per market while warping from its generated slot to `past_cutoff` slots
+/// beyond its cutoff slot, `step` slots at a time (the cutoff slot and the one after it are
+/// always included). Prints the slots where the outcome changes; `csv` gets every step.
+pub fn staleness(
+    snapshot: &Snapshot,
+    direction: SwapDirection,
+    amount: Option<f64>,
+    market: Option<Pubkey>,
+    step: u64,
+    past_cutoff: u64,
+    csv: Option<&Path>,
+) -> eyre::Result<()> {
+    eyre::ensure!(step > 0, "--step must be at least 1");
+    let amount = amount.unwrap_or(match direction {
+        SwapDirection::SolToUsdc => DEFAULT_SWAP_AMOUNT_SOL,
+        SwapDirection::UsdcToSol => DEFAULT_SWAP_AMOUNT_USDC,
+    });
+
+    // every quote below pins its own slot
+    let (mut sim, markets) = Simulator::load_markets(snapshot, market, None)?;
+    let mut wtr = csv.map(|path| WriterBuilder::new().from_path(path)).transpose()?;
+
+    for market in markets {
+        let decoded = match sim.market(&market) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                tracing::warn!("Skipping {market}: {e:#}");
+                continue;
+            }
+        };
+        let (generated, cutoff) = (decoded.generated_slot, decoded.cutoff_slot);
+        let end = cutoff.max(generated).saturating_add(past_cutoff);
+        let mut slots = (generated..=end).step_by(step as usize).collect::<Vec<_>>();
+        slots.extend(
+            [cutoff, cutoff + 1, end].into_iter().filter(|s| (generated..=end).contains(s)),
+        );
+        slots.sort();
+        slots.dedup();
+
+        println!(
+            "== {market} generated slot {generated}, cutoff slot {cutoff} (+{}) ==",
+            cutoff.saturating_sub(generated)
+        );
+        let mut last: Option<(Option<f64>, Option<String>)> = None;
+        for slot in slots {
+            sim = sim.with_slot(Some(slot));
+            let r = sim.quote_market(&market, direction, amount)?;
+            let outcome = (r.out_amount, r.error.clone());
+            if last.as_ref() != Some(&outcome) {
+                let offset = slot - generated;
+                match (&r.out_amount, &r.error) {
+                    (Some(out), _) => println!("  slot {slot} (+{offset}): {amount} -> {out}"),
+                    (None, error) => println!(
+                        "  slot {slot} (+{offset}): fails: {}{}",
+                        error.as_deref().unwrap_or("unknown"),
+                        r.error_name.as_deref().map(|name| format!(" ({name})")).unwrap_or_default()
+                    ),
+                }
+                last = Some(outcome);
+            }
+            if let Some(w) = wtr.as_mut() {
+                w.serialize(StalenessRow {
+                    market: r.market,
+                    slot,
+                    slots_since_generated: slot - generated,
+                    generated_slot: generated,
+                    cutoff_slot: cutoff,
+                    in_amount: r.in_amount,
+                    out_amount: r.out_amount,
+                    error: r.error,
+                    error_name: r.error_name,
+                    compute_units: r.compute_units,
+                })?;
+            }
+        }
+    }
+    if let Some(mut w) = wtr {
+        w.flush()?;
+    }
+    Ok(())
+}
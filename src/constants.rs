@@ -5,6 +5,20 @@ pub const DEFAULT_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
 pub const SOLFI_PROGRAM: Pubkey = pubkey!("SoLFiHG9TfgtdUXUjWAxi3LtvYuFyDLVhBWxdMZxyCe");
 pub const WSOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
 pub const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+pub const USDT: Pubkey = pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
+
+pub const KNOWN_MINTS: &[(Pubkey, &str)] = &[(WSOL, "SOL"), (USDC, "USDC"), (USDT, "USDT")];
+
+/// SolFi custom error codes seen in simulation. The program's error enum is not published, so
+/// the names describe the conditions the codes were observed under.
+pub const SOLFI_ERRORS: &[(u32, &str)] = &[
+    (0x10, "market inactive"),
+    (SOLFI_INSUFFICIENT_LIQUIDITY, "insufficient liquidity"),
+    (0x17, "stale quote"),
+];
+
+/// The SolFi error code a swap fails with when it takes more than the pool can give.
+pub const SOLFI_INSUFFICIENT_LIQUIDITY: u32 = 0x12;
 
 pub const SOLFI_MARKETS: &[Pubkey] = &[
     pubkey!("5guD4Uz462GT4Y4gEuqyGsHZ59JGxFN4a3rF6KWguMcJ"),
@@ -12,3 +26,5 @@ pub const SOLFI_MARKETS: &[Pubkey] = &[
     pubkey!("AHhiY6GAKfBkvseQDQbBC7qp3fTRNpyZccuEdYSdPFEf"),
     pubkey!("CAPhoEse9xEH95XmdnJjYrZdNCA8xfUWdy3aWymHa1Vj"),
 ];
+
+pub const SOLFI_PROGRAM_PATH: &str = "data/solfi.so";
@@ -0,0 +1,15 @@
+//! Local LiteSVM simulator for SolFi markets.
+//!
+//! [`Simulator`] loads a snapshot of SolFi accounts plus the SolFi program once and quotes swaps
+//! against it; the `solfi-sim` binary is a thin CLI around it.
+
+pub mod book;
+pub mod constants;
+pub mod limits;
+pub mod route;
+pub mod simulator;
+pub mod swap;
+pub mod types;
+pub mod utils;
+
+pub use simulator::{ExactOutResult, Session, Simulator, SwapResult};
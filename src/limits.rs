@@ -0,0 +1,231 @@
+use crate::Simulator;
+use crate::route::route;
+use crate::swap::SwapDirection;
+use serde::Serialize;
+use solana_pubkey::Pubkey;
+
+/// Market name of results over all the simulator's pools together.
+pub const COMBINED_MARKET: &str = "all";
+
+// doublings of the probe size before giving up on finding a size that breaks the limit
+const MAX_DOUBLINGS: u32 = 64;
+// halvings of the probe size before giving up on finding a size within the limit; by then it is
+// well below one atomic unit of either token
+const MAX_HALVINGS: u32 = 40;
+// bisection stops once the bracket is this small relative to its upper end
+const RELATIVE_TOLERANCE: f64 = 1e-6;
+
+/// The worst average price a trade may get.
+#[derive(Debug, Clone, Copy)]
+pub enum PriceLimit {
+    /// At most this many basis points worse than mid.
+    SlippageBps(f64),
+    /// Quote per base: a floor when selling the base, a ceiling when buying it.
+    Price(f64),
+}
+
+/// The largest trade found by [`max_size`] whose average price stays within the limit.
+#[derive(Serialize, Debug, Clone)]
+pub struct MaxSize {
+    pub market: String,
+    /// Only computed for a slippage limit, which is relative to it.
+    pub mid_price: Option<f64>,
+    pub limit_price: f64,
+    pub in_amount: f64,
+    pub out_amount: f64,
+    pub average_price: f64,
+    /// How much worse than mid the average price is, for a slippage limit.
+    pub slippage_bps: Option<f64>,
+    /// Number of trade sizes simulated.
+    pub iterations: u32,
+}
+
+/// Mid between the average prices of selling `probe` base and of buying about as much, on one
+/// market or (`None`) across the simulator's pools, best bid and best ask.
+pub fn mid_price(
+    sim: &Simulator,
+    market: Option<&Pubkey>,
+    probe: f64,
+) -> eyre::Result<Option<f64>> {
+    let markets = match market {
+        Some(market) => vec![*market],
+        None => sim.markets().to_vec(),
+    };
+    let (mut bid, mut ask) = (None::<f64>, None::<f64>);
+    for market in &markets {
+        let Some(quote_out) = sim.quote_market(market, SwapDirection::SolToUsdc, probe)?.out_amount
+        else {
+            continue;
+        };
+        let market_bid = quote_out / probe;
+        bid = Some(bid.map_or(market_bid, |b| b.max(market_bid)));
+        let spend = probe * market_bid;
+        if let Some(base_out) =
+            sim.quote_market(market, SwapDirection::UsdcToSol, spend)?.out_amount
+        {
+            let market_ask = spend / base_out;
+            ask = Some(ask.map_or(market_ask, |a| a.min(market_ask)));
+        }
+    }
+    Ok(bid.zip(ask).map(|(bid, ask)| (bid + ask) / 2.0))
+}
+
+/// Bisects the input amount (base when selling it, quote when buying) for the largest trade whose
+/// average price is within `limit`, on one market or (`None`) split across the simulator's pools
+/// as [`route`] splits it into `chunks`. The search starts from a trade sized like `probe`, and
+/// halves it first if even that breaks the limit. Failed swaps count as breaking the limit; any
+/// other error is returned.
+pub fn max_size(
+    sim: &Simulator,
+    market: Option<&Pubkey>,
+    direction: SwapDirection,
+    limit: PriceLimit,
+    probe: f64,
+    chunks: u32,
+) -> eyre::Result<Option<MaxSize>> {
+    eyre::ensure!(probe > 0.0, "probe size must be positive");
+    let (mid, limit_price) = match limit {
+        PriceLimit::Price(price) => (None, price),
+        PriceLimit::SlippageBps(bps) => {
+            let Some(mid) = mid_price(sim, market, probe)? else {
+                return Ok(None);
+            };
+            let limit_price = match direction {
+                SwapDirection::SolToUsdc => mid * (1.0 - bps / 10_000.0),
+                SwapDirection::UsdcToSol => mid * (1.0 + bps / 10_000.0),
+            };
+            (Some(mid), limit_price)
+        }
+    };
+
+    let mut iterations = 0;
+    let trade = |amount: f64| -> eyre::Result<Option<(f64, f64)>> {
+        iterations += 1;
+        let out = match market {
+            Some(market) => sim.quote_market(market, direction, amount)?.out_amount,
+            None => route(sim, direction, amount, chunks)?.map(|r| r.out_amount),
+        };
+        let Some(out) = out.filter(|out| *out > 0.0) else {
+            return Ok(None);
+        };
+        let average = direction.average_price(amount, out);
+        let within = match direction {
+            SwapDirection::SolToUsdc => average >= limit_price,
+            SwapDirection::UsdcToSol => average <= limit_price,
+        };
+        Ok(within.then_some((out, average)))
+    };
+
+    // the first trade is sized like the probe, in the input token
+    let start = match direction {
+        SwapDirection::SolToUsdc => probe,
+        SwapDirection::UsdcToSol => probe * mid.unwrap_or(limit_price),
+    };
+    let Some((in_amount, (out_amount, average_price))) = search_max(start, trade)? else {
+        return Ok(None);
+    };
+
+    let slippage_bps = mid.map(|mid| match direction {
+        SwapDirection::SolToUsdc => (mid - average_price) / mid * 10_000.0,
+        SwapDirection::UsdcToSol => (average_price - mid) / mid * 10_000.0,
+    });
+    Ok(Some(MaxSize {
+        market: market.map_or_else(|| COMBINED_MARKET.to_string(), |m| m.to_string()),
+        mid_price: mid,
+        limit_price,
+        in_amount,
+        out_amount,
+        average_price,
+        slippage_bps,
+        iterations,
+    }))
+}
+
+// the largest amount `trade` accepts, with what it returned for it: doubles `start` while it is
+// accepted, or halves it until it is, then bisects the bracket. `None` when no amount is accepted.
+fn search_max<T>(
+    start: f64,
+    mut trade: impl FnMut(f64) -> eyre::Result<Option<T>>,
+) -> eyre::Result<Option<(f64, T)>> {
+    let (mut best, mut hi) = match trade(start)? {
+        Some(result) => {
+            let mut best = (start, result);
+            let mut hi = start * 2.0;
+            let mut doublings = 0;
+            while let Some(result) = trade(hi)? {
+                best = (hi, result);
+                hi *= 2.0;
+                doublings += 1;
+                eyre::ensure!(doublings < MAX_DOUBLINGS, "no trade size breaks the limit");
+            }
+            (best, hi)
+        }
+        None => {
+            let mut hi = start;
+            let mut halvings = 0;
+            loop {
+                let lo = hi / 2.0;
+                if let Some(result) = trade(lo)? {
+                    break ((lo, result), hi);
+                }
+                halvings += 1;
+                if halvings == MAX_HALVINGS {
+                    return Ok(None);
+                }
+                hi = lo;
+            }
+        }
+    };
+    while hi - best.0 > hi * RELATIVE_TOLERANCE {
+        let amount = best.0 + (hi - best.0) / 2.0;
+        match trade(amount)? {
+            Some(result) => best = (amount, result),
+            None => hi = amount,
+        }
+    }
+    Ok(Some(best))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // accepts any trade up to `max`
+    fn up_to(max: f64) -> impl FnMut(f64) -> eyre::Result<Option<f64>> {
+        move |amount| Ok((amount <= max).then_some(amount))
+    }
+
+    fn assert_close(found: f64, max: f64) {
+        assert!(found <= max && max - found <= max * RELATIVE_TOLERANCE, "{found} vs {max}");
+    }
+
+    #[test]
+    fn grows_from_a_start_within_the_limit() {
+        let (amount, result) = search_max(0.01, up_to(37.5)).unwrap().unwrap();
+        assert_close(amount, 37.5);
+        assert_eq!(amount, result);
+    }
+
+    #[test]
+    fn shrinks_from_a_start_that_breaks_the_limit() {
+        let (amount, _) = search_max(0.01, up_to(0.0007)).unwrap().unwrap();
+        assert_close(amount, 0.0007);
+    }
+
+    #[test]
+    fn gives_up_when_nothing_is_within_the_limit() {
+        let mut calls = 0;
+        let found = search_max(0.01, |_| {
+            calls += 1;
+            Ok(None::<()>)
+        })
+        .unwrap();
+        assert!(found.is_none());
+        assert_eq!(calls, MAX_HALVINGS + 1);
+    }
+
+    #[test]
+    fn fails_when_nothing_breaks_the_limit() {
+        assert!(search_max(0.01, up_to(f64::INFINITY)).is_err());
+    }
+}
@@ -1,22 +1,35 @@
 mod args;
 mod cmd;
-mod constants;
-mod swap;
-mod types;
-mod utils;
 
 use crate::args::{App, Command};
 use crate::cmd::{
+    backtest,
+    book,
+    diff_market,
+    discover_markets,
+    export_snapshot,
     fetch_and_persist_accounts,
     fetch_and_persist_single_market,
+    import_snapshot,
+    inspect_market,
+    print_max_size,
+    quote_exact_out,
+    record,
+    route_order,
     display_cutoffs,
     simulate_all as simulate,
     run_spreads,
+    sequence,
+    staleness,
+    RecordOptions,
 };
-use crate::constants::DEFAULT_RPC_URL;
 use clap::Parser;
 use dotenv::dotenv;
+use solfi_sim::constants::DEFAULT_RPC_URL;
+use solfi_sim::limits::PriceLimit;
 use solana_pubkey::Pubkey;
+use solfi_sim::types::{Recording, Snapshot};
+use std::time::Duration;
 use std::str::FromStr;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -32,57 +45,117 @@ async fn main() -> eyre::Result<()> {
     let cmd = App::parse().command;
 
     match cmd {
-        Command::FetchAccounts { market, market_token_quote, market_token_base } => {
-            let rpc_url = {
-                let _ = dotenv().ok();
-                std::env::var("RPC_URL").ok().filter(|url| !url.trim().is_empty()).unwrap_or_else(
-                    || {
-                        tracing::warn!("No RPC_URL found in env. Using {}", DEFAULT_RPC_URL);
-                        DEFAULT_RPC_URL.to_string()
-                    },
-                )
-            };
+        Command::FetchAccounts { market, label, format } => {
+            let rpc_url = rpc_url();
 
-            match (market, market_token_quote, market_token_base) {
-                (Some(m), Some(q), Some(b)) => {
+            match market {
+                Some(m) => {
                     let m = Pubkey::from_str(&m)?;
-                    let q = Pubkey::from_str(&q)?;
-                    let b = Pubkey::from_str(&b)?;
-                    fetch_and_persist_single_market(rpc_url, m, q, b).await?
-                }
-                (None, None, None) => {
-                    fetch_and_persist_accounts(rpc_url).await?
+                    fetch_and_persist_single_market(rpc_url, m, label, format).await?
                 }
-                _ => {
-                    eyre::bail!("When using --market mode, you must provide all of: --market, --market-token-quote, --market-token-base");
+                None => {
+                    fetch_and_persist_accounts(rpc_url, label, format).await?
                 }
             }
         }
-        Command::Cutoffs => display_cutoffs(),
+        Command::DiscoverMarkets => discover_markets(rpc_url()).await?,
+        Command::ImportSnapshot { dir, program, label, slot, format } => {
+            import_snapshot(&dir, program.as_deref(), label, slot, format)?
+        }
+        Command::ExportSnapshot { out_dir, snapshot } => {
+            export_snapshot(&Snapshot::resolve(snapshot.as_deref())?, &out_dir)?
+        }
+        Command::Record { market, label, interval_ms, max_segment_mb, max_total_mb, max_polls } => {
+            let markets = market.iter().map(|m| Pubkey::from_str(m)).collect::<Result<Vec<_>, _>>()?;
+            let options = RecordOptions {
+                interval: Duration::from_millis(interval_ms),
+                max_segment_bytes: max_segment_mb * 1024 * 1024,
+                max_total_bytes: max_total_mb * 1024 * 1024,
+                max_polls,
+            };
+            record(rpc_url(), markets, label, options).await?
+        }
+        Command::Backtest { recording, usdc_sizes, sol_sizes, direction, market, csv } => {
+            let market = market.as_deref().map(Pubkey::from_str).transpose()?;
+            let recording = Recording::open(&recording)?;
+            backtest(&recording, &usdc_sizes, &sol_sizes, &direction, market, csv.as_deref())?
+        }
+        Command::Cutoffs { snapshot } => display_cutoffs(&Snapshot::resolve(snapshot.as_deref())?)?,
+        Command::InspectMarket { market, snapshot } => {
+            inspect_market(&Snapshot::resolve(snapshot.as_deref())?, Pubkey::from_str(&market)?)?
+        }
+        Command::DiffMarket { market, before, after } => {
+            diff_market(&Snapshot::open(&before)?, &Snapshot::open(&after)?, Pubkey::from_str(&market)?)?
+        }
         Command::Spreads {
             starting_usdc,
             sizes,
             csv,
             market,
-            market_token_quote,
-            market_token_base,
             slot,
+            snapshot,
+            method,
+            probe,
         } => {
-            let csv_path = csv.as_ref().map(|p| p.as_path());
-            run_spreads(
-                starting_usdc,
-                sizes,
-                csv_path,
-                market.as_deref(),
-                market_token_quote.as_deref(),
-                market_token_base.as_deref(),
-                slot,
-            )?;
+            let csv_path = csv.as_deref();
+            let snapshot = Snapshot::resolve(snapshot.as_deref())?;
+            run_spreads(&snapshot, starting_usdc, sizes, csv_path, market.as_deref(), slot, &method, probe)?;
         }
-        Command::Simulate { amount, direction, slot, ignore_errors } => {
-            simulate(direction, amount, slot, ignore_errors, true)?;
+        Command::QuoteExactOut { amount, direction, market, slot, snapshot } => {
+            let market = market.as_deref().map(Pubkey::from_str).transpose()?;
+            let snapshot = Snapshot::resolve(snapshot.as_deref())?;
+            quote_exact_out(&snapshot, direction, amount, market, slot)?
+        }
+        Command::MaxSize {
+            direction,
+            max_slippage_bps,
+            limit_price,
+            market,
+            probe,
+            chunks,
+            slot,
+            snapshot,
+        } => {
+            let limit = match (max_slippage_bps, limit_price) {
+                (Some(bps), _) => PriceLimit::SlippageBps(bps),
+                (None, Some(price)) => PriceLimit::Price(price),
+                (None, None) => unreachable!("clap requires one of the limits"),
+            };
+            let market = market.as_deref().map(Pubkey::from_str).transpose()?;
+            let snapshot = Snapshot::resolve(snapshot.as_deref())?;
+            print_max_size(&snapshot, direction, limit, market, probe, chunks, slot)?
+        }
+        Command::Route { amount, direction, chunks, slot, snapshot } => {
+            let snapshot = Snapshot::resolve(snapshot.as_deref())?;
+            route_order(&snapshot, direction, amount, chunks, slot)?
+        }
+        Command::Sequence { swap, market, repeat, csv, snapshot } => {
+            let market = market.as_deref().map(Pubkey::from_str).transpose()?;
+            let snapshot = Snapshot::resolve(snapshot.as_deref())?;
+            sequence(&snapshot, &swap, market, repeat, csv.as_deref())?
+        }
+        Command::Staleness { amount, direction, market, step, past_cutoff, csv, snapshot } => {
+            let market = market.as_deref().map(Pubkey::from_str).transpose()?;
+            let snapshot = Snapshot::resolve(snapshot.as_deref())?;
+            staleness(&snapshot, direction, amount, market, step, past_cutoff, csv.as_deref())?
+        }
+        Command::Book { sizes, market, format, snapshot } => {
+            let market = market.as_deref().map(Pubkey::from_str).transpose()?;
+            book(&Snapshot::resolve(snapshot.as_deref())?, &sizes, market, format)?
+        }
+        Command::Simulate { amount, direction, slot, ignore_errors, snapshot } => {
+            let snapshot = Snapshot::resolve(snapshot.as_deref())?;
+            simulate(&snapshot, direction, amount, slot, ignore_errors, true)?;
         }
     }
 
     Ok(())
 }
+
+fn rpc_url() -> String {
+    let _ = dotenv().ok();
+    std::env::var("RPC_URL").ok().filter(|url| !url.trim().is_empty()).unwrap_or_else(|| {
+        tracing::warn!("No RPC_URL found in env. Using {}", DEFAULT_RPC_URL);
+        DEFAULT_RPC_URL.to_string()
+    })
+}
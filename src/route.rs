@@ -0,0 +1,190 @@
+use crate::Simulator;
+use crate::swap::SwapDirection;
+use serde::Serialize;
+
+/// The part of a routed order sent through one pool.
+#[derive(Serialize, Debug, Clone)]
+pub struct RouteLeg {
+    pub market: String,
+    pub in_amount: f64,
+    pub out_amount: f64,
+}
+
+/// An order split across pools by [`route`], next to the best the order does in a single pool.
+#[derive(Serialize, Debug, Clone)]
+pub struct Route {
+    pub in_amount: f64,
+    /// Pools that got part of the order, largest share first.
+    pub legs: Vec<RouteLeg>,
+    pub out_amount: f64,
+    /// The pool giving the most for the whole order, with what it gives.
+    pub best_single: Option<(String, f64)>,
+}
+
+impl Route {
+    /// Extra output of the split over the best single pool, in basis points of the latter.
+    pub fn improvement_bps(&self) -> Option<f64> {
+        self.best_single
+            .as_ref()
+            .filter(|(_, out)| *out > 0.0)
+            .map(|(_, out)| (self.out_amount - out) / out * 10_000.0)
+    }
+}
+
+/// Splits `amount` across the simulator's WSOL/USDC pools in `chunks` equal parts, giving each
+/// part to the pool whose output grows the most from it. Pools do not share state, so a pool's
+/// output for its share is one quote from the snapshot; with concave outputs the greedy split is
+/// optimal up to the chunk size. `None` when the pools together cannot take the order.
+pub fn route(
+    sim: &Simulator,
+    direction: SwapDirection,
+    amount: f64,
+    chunks: u32,
+) -> eyre::Result<Option<Route>> {
+    eyre::ensure!(amount > 0.0 && chunks > 0, "need a positive amount and at least one chunk");
+    let markets = sim.markets();
+    let share = |n: u32| amount * f64::from(n) / f64::from(chunks);
+    let Some(split) =
+        split(markets.len(), chunks, |i, n| Ok(sim.quote(&markets[i], direction, share(n))?.out_amount))?
+    else {
+        return Ok(None);
+    };
+
+    let mut legs = markets
+        .iter()
+        .zip(split.taken.iter().zip(&split.out))
+        .filter(|(_, (n, _))| **n > 0)
+        .map(|(m, (n, o))| RouteLeg { market: m.to_string(), in_amount: share(*n), out_amount: *o })
+        .collect::<Vec<_>>();
+    legs.sort_by(|a, b| b.in_amount.total_cmp(&a.in_amount));
+
+    Ok(Some(Route {
+        in_amount: amount,
+        out_amount: split.out.iter().sum(),
+        legs,
+        best_single: split.best_single.map(|(i, out)| (markets[i].to_string(), out)),
+    }))
+}
+
+/// How [`split`] shared an order out among pools, by index.
+#[derive(Debug, PartialEq)]
+struct Split {
+    /// Chunks given to each pool.
+    taken: Vec<u32>,
+    /// Output of each pool for its chunks.
+    out: Vec<f64>,
+    /// The pool giving the most for all chunks at once, with what it gives.
+    best_single: Option<(usize, f64)>,
+}
+
+// The greedy allocation behind `route`, over `quote(pool, n)`: the output of `n` chunks in a
+// pool, `None` when the swap fails. Never asks for more than `chunks` chunks. `None` once no pool
+// takes another chunk.
+fn split(
+    pools: usize,
+    chunks: u32,
+    mut quote: impl FnMut(usize, u32) -> eyre::Result<Option<f64>>,
+) -> eyre::Result<Option<Split>> {
+    // per pool: chunks taken, output for them, output with one more
+    let mut taken = vec![0u32; pools];
+    let mut out = vec![0.0; pools];
+    let mut next = (0..pools).map(|i| quote(i, 1)).collect::<eyre::Result<Vec<_>>>()?;
+    for _ in 0..chunks {
+        let Some((i, next_out)) = next
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| n.map(|n| (i, n)))
+            .max_by(|(i, a), (j, b)| (a - out[*i]).total_cmp(&(b - out[*j])))
+        else {
+            return Ok(None);
+        };
+        taken[i] += 1;
+        out[i] = next_out;
+        next[i] = if taken[i] < chunks { quote(i, taken[i] + 1)? } else { None };
+    }
+
+    let mut best_single: Option<(usize, f64)> = None;
+    for i in 0..pools {
+        // the whole order in one pool is already known when the greedy split chose only that pool
+        let full = if taken[i] == chunks { Some(out[i]) } else { quote(i, chunks)? };
+        if let Some(full) = full {
+            if best_single.is_none_or(|(_, best)| full > best) {
+                best_single = Some((i, full));
+            }
+        }
+    }
+
+    Ok(Some(Split { taken, out, best_single }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // canned output curves, one per pool, indexed by chunk count
+    fn curves(curves: &[&[Option<f64>]]) -> impl FnMut(usize, u32) -> eyre::Result<Option<f64>> {
+        let curves = curves.iter().map(|c| c.to_vec()).collect::<Vec<_>>();
+        move |i, n| Ok(curves[i][n as usize - 1])
+    }
+
+    #[test]
+    fn gives_each_chunk_to_the_largest_marginal_output() {
+        // marginals: pool 0 gives 10, 6, 2; pool 1 gives 8, 7, 1
+        let split = split(2, 3, curves(&[
+            &[Some(10.0), Some(16.0), Some(18.0)],
+            &[Some(8.0), Some(15.0), Some(16.0)],
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(split.taken, vec![1, 2]);
+        assert_eq!(split.out, vec![10.0, 15.0]);
+        assert_eq!(split.best_single, Some((0, 18.0)));
+    }
+
+    #[test]
+    fn stops_giving_chunks_to_a_pool_once_it_fails() {
+        // pool 0 is the better pool but cannot take a second chunk; pool 1 fails outright
+        let split = split(3, 3, curves(&[
+            &[Some(10.0), None, None],
+            &[None, None, None],
+            &[Some(5.0), Some(10.0), Some(15.0)],
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(split.taken, vec![1, 0, 2]);
+        assert_eq!(split.out, vec![10.0, 0.0, 10.0]);
+        assert_eq!(split.best_single, Some((2, 15.0)));
+    }
+
+    #[test]
+    fn gives_up_when_no_pool_takes_the_rest() {
+        let split = split(2, 3, curves(&[&[Some(10.0), None, None], &[Some(5.0), None, None]])).unwrap();
+        assert_eq!(split, None);
+    }
+
+    #[test]
+    fn passes_quote_errors_on() {
+        let err = split(2, 3, |_, _| Err(eyre::eyre!("rpc down"))).unwrap_err();
+        assert_eq!(err.to_string(), "rpc down");
+    }
+
+    #[test]
+    fn reuses_the_split_when_one_pool_takes_every_chunk() {
+        let mut asked = vec![];
+        let mut canned = curves(&[
+            &[Some(10.0), Some(20.0), Some(30.0)],
+            &[Some(1.0), Some(2.0), Some(3.0)],
+        ]);
+        let split = split(2, 3, |i, n| {
+            asked.push((i, n));
+            canned(i, n)
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(split.taken, vec![3, 0]);
+        assert_eq!(split.best_single, Some((0, 30.0)));
+        // nothing past the whole order, and the whole order in pool 0 only once
+        assert!(asked.iter().all(|(_, n)| *n <= 3));
+        assert_eq!(asked.iter().filter(|a| **a == (0, 3)).count(), 1);
+    }
+}
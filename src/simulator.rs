@@ -0,0 +1,760 @@
+use crate::constants::{SOLFI_ERRORS, SOLFI_INSUFFICIENT_LIQUIDITY, SOLFI_PROGRAM, USDC, WSOL};
+use crate::swap::{SwapDirection, create_swap_ix_generic_with_token_program};
+use crate::types::{AccountWithAddress, FetchMetadata, Snapshot, SolfiMarket};
+use crate::utils::{
+    account_owner_program, read_mint_decimals_generic, read_token_account_mint,
+    token_balance_generic,
+};
+use eyre::{WrapErr, eyre};
+use litesvm::LiteSVM;
+use serde::{Serialize, Serializer};
+use solana_account::Account;
+use solana_keypair::Keypair;
+use solana_pubkey::Pubkey;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::native_token::sol_to_lamports;
+use solana_sdk::program_option::COption;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::rent::Rent;
+use solana_sdk::rent_collector::RENT_EXEMPT_RENT_EPOCH;
+use solana_sdk::transaction::TransactionError;
+use solana_signer::Signer;
+use solana_system_interface::instruction::transfer;
+use solana_transaction::Transaction;
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::instruction::sync_native;
+use spl_token::state::{Account as TokenAccount, AccountState};
+use spl_token_2022::state as token2022_state;
+use std::fs;
+
+const SOL_DECIMALS: i32 = 9;
+const USDC_DECIMALS: i32 = 6;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SwapResult {
+    pub market: String,
+    pub in_amount: f64,
+    pub out_amount: Option<f64>,
+    pub error: Option<String>,
+    /// The SolFi custom error code the swap failed with, if that is how it failed.
+    #[serde(skip)]
+    pub error_code: Option<u32>,
+    /// What a SolFi custom error code means, when it is a known one.
+    pub error_name: Option<String>,
+    /// Compute units the SolFi swap instruction consumed, leaving out any SOL wrapping in front
+    /// of it; 0 when the transaction failed before the swap ran.
+    pub compute_units: u64,
+    /// Log messages of the swap transaction, failed or not.
+    #[serde(serialize_with = "join_logs")]
+    pub logs: Vec<String>,
+}
+
+impl SwapResult {
+    /// Quote per base the swap traded at, when it swapped to something.
+    pub fn average_price(&self, direction: SwapDirection) -> Option<f64> {
+        self.out_amount.filter(|out| *out > 0.0).map(|out| direction.average_price(self.in_amount, out))
+    }
+}
+
+// one CSV field per result
+fn join_logs<S: Serializer>(logs: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&logs.join(" | "))
+}
+
+/// The smallest input found by [`Simulator::quote_exact_out`] that swaps to at least `target_out`.
+#[derive(Serialize, Debug, Clone)]
+pub struct ExactOutResult {
+    pub market: String,
+    pub target_out: f64,
+    pub in_amount: Option<f64>,
+    pub out_amount: Option<f64>,
+    /// Quote tokens per base token paid or received.
+    pub price: Option<f64>,
+    /// Number of exact-in swaps simulated.
+    pub iterations: u32,
+    pub error: Option<String>,
+    pub error_name: Option<String>,
+}
+
+/// A LiteSVM preloaded with a snapshot of SolFi accounts and the SolFi program.
+///
+/// Every quote runs on a clone of the loaded state, so quotes never see each other's effects.
+#[derive(Clone)]
+pub struct Simulator {
+    svm: LiteSVM,
+    markets: Vec<Pubkey>,
+    metadata_slot: Option<u64>,
+    slot: Option<u64>,
+}
+
+impl Simulator {
+    /// Loads `accounts` and the SolFi `program`. [`Simulator::quote_all`] goes through every
+    /// WSOL/USDC SolFi market among `accounts`.
+    pub fn new(
+        accounts: Vec<AccountWithAddress>,
+        program: &[u8],
+        metadata: Option<FetchMetadata>,
+    ) -> eyre::Result<Self> {
+        let mut svm = LiteSVM::new()
+            .with_sysvars()
+            .with_precompiles()
+            .with_sigverify(true)
+            .with_spl_programs();
+        let markets = wsol_usdc_markets(&accounts);
+        for acct in accounts {
+            svm.set_account(acct.address, acct.account)?;
+        }
+        svm.add_program(SOLFI_PROGRAM, program);
+
+        Ok(Self {
+            svm,
+            markets,
+            metadata_slot: metadata.map(|m| m.slot()),
+            slot: None,
+        })
+    }
+
+    /// Loads the latest snapshot together with the SolFi program binary.
+    pub fn load() -> eyre::Result<Self> {
+        Self::load_snapshot(&Snapshot::latest()?)
+    }
+
+    /// Like [`Simulator::load`], but for a specific snapshot.
+    pub fn load_snapshot(snapshot: &Snapshot) -> eyre::Result<Self> {
+        let program_path = snapshot.program_path();
+        let program = fs::read(&program_path)
+            .wrap_err_with(|| format!("failed to read {}", program_path.display()))?;
+        snapshot.verify_program(&program);
+        Self::new(snapshot.read_accounts()?, &program, snapshot.metadata())
+    }
+
+    /// Loads `snapshot` along with the markets a command quotes: `market` alone, else every
+    /// WSOL/USDC pool. Quotes are pinned to `slot`, else to the slot [`Self::slot_for`] picks for
+    /// those markets, so they all run at the same slot.
+    pub fn load_markets(
+        snapshot: &Snapshot,
+        market: Option<Pubkey>,
+        slot: Option<u64>,
+    ) -> eyre::Result<(Self, Vec<Pubkey>)> {
+        let sim = Self::load_snapshot(snapshot)?;
+        let markets = market.map_or_else(|| sim.markets().to_vec(), |market| vec![market]);
+        let slot = slot.or_else(|| sim.slot_for(&markets));
+        Ok((sim.with_slot(slot), markets))
+    }
+
+    /// Overwrites accounts in the loaded state, e.g. to step through recorded market states.
+    pub fn set_accounts(&mut self, accounts: impl IntoIterator<Item = AccountWithAddress>) -> eyre::Result<()> {
+        for acct in accounts {
+            self.svm.set_account(acct.address, acct.account)?;
+        }
+        Ok(())
+    }
+
+    pub fn markets(&self) -> &[Pubkey] {
+        &self.markets
+    }
+
+    /// Pins the slot quotes are simulated at. When unset, quotes warp to the market's generated
+    /// slot, falling back to the slot the snapshot was fetched at.
+    pub fn with_slot(mut self, slot: Option<u64>) -> Self {
+        self.slot = slot;
+        self
+    }
+
+    /// The slot the market maker generated the market's current quote at.
+    pub fn generated_slot(&self, market: &Pubkey) -> Option<u64> {
+        self.market(market).ok().map(|m| m.generated_slot)
+    }
+
+    /// The slot quotes on `markets` run at: the pinned slot, else the oldest generated slot among
+    /// them, else the slot the snapshot was fetched at.
+    pub fn slot_for(&self, markets: &[Pubkey]) -> Option<u64> {
+        self.slot
+            .or_else(|| markets.iter().filter_map(|m| self.generated_slot(m)).min())
+            .or(self.metadata_slot)
+    }
+
+    fn fork(&self, slot: Option<u64>) -> LiteSVM {
+        let mut svm = self.svm.clone();
+        if let Some(s) = slot {
+            svm.warp_to_slot(s);
+        }
+        svm
+    }
+
+    /// Quotes `amount` through each of the WSOL/USDC pools, all at the same slot (the oldest
+    /// generated slot among them unless pinned).
+    pub fn quote_all(&self, direction: SwapDirection, amount: f64) -> eyre::Result<Vec<SwapResult>> {
+        let slot = self.slot_for(&self.markets);
+        self.markets.iter().map(|market| self.quote_wsol_usdc(market, direction, amount, slot)).collect()
+    }
+
+    /// Quotes `amount` through a single WSOL/USDC pool.
+    pub fn quote(
+        &self,
+        market: &Pubkey,
+        direction: SwapDirection,
+        amount: f64,
+    ) -> eyre::Result<SwapResult> {
+        self.quote_wsol_usdc(market, direction, amount, self.slot_for(&[*market]))
+    }
+
+    fn quote_wsol_usdc(
+        &self,
+        market: &Pubkey,
+        direction: SwapDirection,
+        in_amount_ui: f64,
+        slot: Option<u64>,
+    ) -> eyre::Result<SwapResult> {
+        let decoded = self.market(market)?;
+        let user_keypair = Keypair::new();
+        let user = user_keypair.pubkey();
+        let mut svm = self.fork(slot);
+
+        let (to_mint, from_decimals, to_decimals) = match direction {
+            SwapDirection::SolToUsdc => (&USDC, SOL_DECIMALS, USDC_DECIMALS),
+            SwapDirection::UsdcToSol => (&WSOL, USDC_DECIMALS, SOL_DECIMALS),
+        };
+        let amount_in_atomic = (in_amount_ui * 10f64.powi(from_decimals)) as u64;
+
+        let fee_lamports = sol_to_lamports(1.0);
+        let wsol_ata = get_associated_token_address(&user, &WSOL);
+        let usdc_ata = get_associated_token_address(&user, &USDC);
+        match direction {
+            SwapDirection::SolToUsdc => {
+                svm.airdrop(&user, amount_in_atomic + fee_lamports)
+                    .map_err(|e| eyre!("failed to airdrop SOL: {}", e.err))?;
+                svm.set_account(usdc_ata, mk_ata_account(&USDC, &user, 0))?;
+            }
+            SwapDirection::UsdcToSol => {
+                svm.airdrop(&user, fee_lamports)
+                    .map_err(|e| eyre!("failed to airdrop SOL: {}", e.err))?;
+                svm.set_account(usdc_ata, mk_ata_account(&USDC, &user, amount_in_atomic))?;
+            }
+        }
+        // pre-create user ATAs in the SVM (avoid calling ATA CPI)
+        svm.set_account(wsol_ata, mk_native_wsol_account(&user))?;
+
+        let mut instructions = vec![];
+        if direction == SwapDirection::SolToUsdc {
+            // Wrap SOL: system transfer lamports into WSOL ATA, then sync_native
+            instructions.push(transfer(&user, &wsol_ata, amount_in_atomic));
+            instructions.push(sync_native(&spl_token::id(), &wsol_ata)?);
+        }
+        // swap through the vaults recorded in the market account
+        instructions.push(create_swap_ix_generic_with_token_program(
+            direction,
+            market,
+            &user,
+            &decoded.base_vault,
+            &decoded.quote_vault,
+            &WSOL,
+            &USDC,
+            &spl_token::id(),
+            amount_in_atomic,
+        ));
+
+        let to_ata = get_associated_token_address(&user, to_mint);
+        execute_swap(
+            &mut svm,
+            &user_keypair,
+            &instructions,
+            market,
+            in_amount_ui,
+            &to_ata,
+            to_decimals,
+            &spl_token::id(),
+        )
+    }
+
+    /// Decodes the mints and vaults of a market in the loaded snapshot.
+    pub fn market(&self, market: &Pubkey) -> eyre::Result<SolfiMarket> {
+        decode_market(&self.svm, market)
+    }
+
+    /// Quotes `amount_ui` through any SolFi market, using the vaults recorded in its account.
+    pub fn quote_market(
+        &self,
+        market: &Pubkey,
+        direction: SwapDirection,
+        amount_ui: f64,
+    ) -> eyre::Result<SwapResult> {
+        let decoded = self.market(market)?;
+        self.quote_with_vaults(market, &decoded.quote_vault, &decoded.base_vault, direction, amount_ui)
+    }
+
+    /// Finds the smallest input that swaps to at least `out_amount_ui` through any SolFi market,
+    /// by bisecting the exact-in swap down to one atomic unit of input. Starts from the input the
+    /// market's reference price implies and doubles it until the target is met. Running out of
+    /// liquidity counts as too much input; any other failure ends the search with that error.
+    pub fn quote_exact_out(
+        &self,
+        market: &Pubkey,
+        direction: SwapDirection,
+        out_amount_ui: f64,
+    ) -> eyre::Result<ExactOutResult> {
+        eyre::ensure!(out_amount_ui > 0.0, "exact-out target must be positive");
+        let decoded = self.market(market)?;
+        let (in_decimals, out_decimals) = match direction {
+            SwapDirection::UsdcToSol => (decoded.quote_decimals, decoded.base_decimals),
+            SwapDirection::SolToUsdc => (decoded.base_decimals, decoded.quote_decimals),
+        };
+        let in_scale = 10f64.powi(in_decimals as i32);
+        let out_scale = 10f64.powi(out_decimals as i32);
+        let target = (out_amount_ui * out_scale).round();
+
+        // a market without a reference price still gets searched, from one whole input token
+        let estimate = match direction {
+            SwapDirection::UsdcToSol => out_amount_ui * decoded.price_ui(),
+            SwapDirection::SolToUsdc => out_amount_ui / decoded.price_ui(),
+        } * in_scale;
+        let start = if estimate.is_finite() && estimate >= 1.0 { estimate as u64 } else { in_scale as u64 };
+
+        let mut iterations = 0;
+        let best = search_exact_out(start, |amount| {
+            iterations += 1;
+            let r = self.quote_market(market, direction, amount as f64 / in_scale)?;
+            let trial = match r.out_amount {
+                Some(out) if (out * out_scale).round() >= target => Trial::Enough,
+                Some(_) => Trial::TooLittle,
+                None if r.error_code == Some(SOLFI_INSUFFICIENT_LIQUIDITY) => Trial::Enough,
+                None => Trial::Failed,
+            };
+            Ok((trial, r))
+        })?
+        .ok_or_else(|| eyre!("no input reaches {out_amount_ui} on {market}"))?;
+
+        let price = best.average_price(direction);
+        Ok(ExactOutResult {
+            market: market.to_string(),
+            target_out: out_amount_ui,
+            in_amount: best.out_amount.map(|_| best.in_amount),
+            out_amount: best.out_amount,
+            price,
+            iterations,
+            error: best.error,
+            error_name: best.error_name,
+        })
+    }
+
+    /// Quotes `amount_ui` through any SolFi market given its vaults. Works with both SPL Token
+    /// and Token-2022 vaults.
+    pub fn quote_with_vaults(
+        &self,
+        market: &Pubkey,
+        market_vault_quote: &Pubkey,
+        market_vault_base: &Pubkey,
+        direction: SwapDirection,
+        amount_ui: f64,
+    ) -> eyre::Result<SwapResult> {
+        let mut svm = self.fork(self.slot_for(&[*market]));
+        swap_with_vaults(&mut svm, market, market_vault_quote, market_vault_base, direction, amount_ui)
+    }
+
+    /// Starts a [`Session`] on a fork of the loaded state, at the slot quotes on `markets` run at.
+    pub fn session(&self, markets: &[Pubkey]) -> Session {
+        Session { svm: self.fork(self.slot_for(markets)) }
+    }
+}
+
+/// A fork of the loaded state that keeps the effects of every swap made on it, for modelling
+/// consecutive trades (e.g. the slices of a TWAP order) against the same pools.
+pub struct Session {
+    svm: LiteSVM,
+}
+
+impl Session {
+    pub fn market(&self, market: &Pubkey) -> eyre::Result<SolfiMarket> {
+        decode_market(&self.svm, market)
+    }
+
+    /// Swaps `amount_ui` through any SolFi market, leaving the pool as the swap left it.
+    pub fn swap(
+        &mut self,
+        market: &Pubkey,
+        direction: SwapDirection,
+        amount_ui: f64,
+    ) -> eyre::Result<SwapResult> {
+        let decoded = self.market(market)?;
+        let (quote_vault, base_vault) = (decoded.quote_vault, decoded.base_vault);
+        swap_with_vaults(&mut self.svm, market, &quote_vault, &base_vault, direction, amount_ui)
+    }
+
+    /// Current base and quote vault balances of a market, in whole tokens.
+    pub fn vault_balances(&self, market: &Pubkey) -> eyre::Result<(f64, f64)> {
+        let decoded = self.market(market)?;
+        let balance = |vault: &Pubkey, decimals: u32| -> eyre::Result<f64> {
+            let token_program = account_owner_program(&self.svm, vault)?;
+            let atoms = token_balance_generic(&self.svm, vault, &token_program)?;
+            Ok(atoms as f64 / 10f64.powi(decimals as i32))
+        };
+        Ok((
+            balance(&decoded.base_vault, decoded.base_decimals)?,
+            balance(&decoded.quote_vault, decoded.quote_decimals)?,
+        ))
+    }
+}
+
+// WSOL/USDC SolFi markets among `accounts`, in address order
+fn wsol_usdc_markets(accounts: &[AccountWithAddress]) -> Vec<Pubkey> {
+    let mut markets = accounts
+        .iter()
+        .filter(|a| {
+            SolfiMarket::from_account(&a.account)
+                .is_ok_and(|m| m.base_mint == WSOL && m.quote_mint == USDC)
+        })
+        .map(|a| a.address)
+        .collect::<Vec<_>>();
+    markets.sort();
+    markets
+}
+
+fn decode_market(svm: &LiteSVM, market: &Pubkey) -> eyre::Result<SolfiMarket> {
+    let acct = svm
+        .get_account(market)
+        .ok_or_else(|| eyre!("market {market} is not in the loaded snapshot"))?;
+    SolfiMarket::from_account(&acct)
+        .wrap_err_with(|| format!("failed to decode SolFi market {market}"))
+}
+
+// swaps through any SolFi market given its vaults, on whatever state `svm` holds
+fn swap_with_vaults(
+    svm: &mut LiteSVM,
+    market: &Pubkey,
+    market_vault_quote: &Pubkey,
+    market_vault_base: &Pubkey,
+    direction: SwapDirection,
+    amount_ui: f64,
+) -> eyre::Result<SwapResult> {
+    let user_keypair = Keypair::new();
+    let user = user_keypair.pubkey();
+
+    let quote_token_program = account_owner_program(svm, market_vault_quote)?;
+    let base_token_program = account_owner_program(svm, market_vault_base)?;
+    eyre::ensure!(quote_token_program == base_token_program, "vault token programs mismatch");
+    let token_program_id = quote_token_program;
+
+    let quote_mint = read_token_account_mint(svm, market_vault_quote)?;
+    let base_mint = read_token_account_mint(svm, market_vault_base)?;
+    let quote_dec = read_mint_decimals_generic(svm, &quote_mint, &token_program_id)?;
+    let base_dec = read_mint_decimals_generic(svm, &base_mint, &token_program_id)?;
+
+    let fee_lamports = sol_to_lamports(1.0);
+    svm.airdrop(&user, fee_lamports).map_err(|e| eyre!("failed to airdrop SOL: {}", e.err))?;
+
+    let user_base_ata = get_associated_token_address(&user, &base_mint);
+    let user_quote_ata = get_associated_token_address(&user, &quote_mint);
+
+    let (amount_in_atomic, input_is_quote) = match direction {
+        SwapDirection::UsdcToSol => (to_units(amount_ui, quote_dec)?, true),
+        SwapDirection::SolToUsdc => (to_units(amount_ui, base_dec)?, false),
+    };
+    let base_amount = if input_is_quote { 0 } else { amount_in_atomic };
+    let quote_amount = if input_is_quote { amount_in_atomic } else { 0 };
+    svm.set_account(
+        user_base_ata,
+        mk_ata_account_with_owner(&base_mint, &user, base_amount, &token_program_id),
+    )?;
+    svm.set_account(
+        user_quote_ata,
+        mk_ata_account_with_owner(&quote_mint, &user, quote_amount, &token_program_id),
+    )?;
+
+    let (to_ata, to_decimals) =
+        if input_is_quote { (user_base_ata, base_dec) } else { (user_quote_ata, quote_dec) };
+
+    let ix = create_swap_ix_generic_with_token_program(
+        direction,
+        market,
+        &user,
+        market_vault_base,
+        market_vault_quote,
+        &base_mint,
+        &quote_mint,
+        &token_program_id,
+        amount_in_atomic,
+    );
+
+    execute_swap(
+        svm,
+        &user_keypair,
+        &[ix],
+        market,
+        amount_ui,
+        &to_ata,
+        to_decimals as i32,
+        &token_program_id,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_swap(
+    svm: &mut LiteSVM,
+    user_keypair: &Keypair,
+    instructions: &[Instruction],
+    market: &Pubkey,
+    in_amount_ui: f64,
+    to_ata: &Pubkey,
+    to_decimals: i32,
+    token_program: &Pubkey,
+) -> eyre::Result<SwapResult> {
+    let balance_before = token_balance_generic(svm, to_ata, token_program)?;
+
+    let tx = Transaction::new_with_payer(instructions, Some(&user_keypair.pubkey()));
+    let signed_tx = Transaction::new(&[user_keypair], tx.message, svm.latest_blockhash());
+
+    match svm.send_transaction(signed_tx) {
+        Ok(meta) => {
+            let balance_after = token_balance_generic(svm, to_ata, token_program)?;
+            let out_amount_atomic = balance_after.saturating_sub(balance_before);
+            Ok(SwapResult {
+                market: market.to_string(),
+                in_amount: in_amount_ui,
+                out_amount: Some(out_amount_atomic as f64 / 10f64.powi(to_decimals)),
+                error: None,
+                error_code: None,
+                error_name: None,
+                compute_units: swap_compute_units(&meta.logs),
+                logs: meta.logs,
+            })
+        }
+        Err(err) => {
+            // the swap is always the last instruction; earlier ones only wrap SOL
+            let error_code = match err.err {
+                TransactionError::InstructionError(index, InstructionError::Custom(code))
+                    if usize::from(index) + 1 == instructions.len() =>
+                {
+                    Some(code)
+                }
+                _ => None,
+            };
+            let error_name = error_code.and_then(|code| {
+                SOLFI_ERRORS.iter().find(|(c, _)| *c == code).map(|(_, name)| name.to_string())
+            });
+            Ok(SwapResult {
+                market: market.to_string(),
+                in_amount: in_amount_ui,
+                out_amount: None,
+                error: Some(err.err.to_string()),
+                error_code,
+                error_name,
+                compute_units: swap_compute_units(&err.meta.logs),
+                logs: err.meta.logs,
+            })
+        }
+    }
+}
+
+/// How one input tried by an exact-out search turned out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Trial {
+    TooLittle,
+    /// Reaches the target, or asks for more than the pool can give.
+    Enough,
+    /// Fails for any other reason, which more or less input will not fix.
+    Failed,
+}
+
+// The search behind `quote_exact_out`: doubles the input from `start` until a trial is enough,
+// then bisects down to one unit for the smallest input that is. A failed trial ends the search.
+// Returns the result of the deciding trial, or `None` when doubling runs out of `u64`.
+fn search_exact_out<T>(
+    start: u64,
+    mut trial: impl FnMut(u64) -> eyre::Result<(Trial, T)>,
+) -> eyre::Result<Option<T>> {
+    let (mut lo, mut hi) = (0u64, start.max(1));
+    let (mut outcome, mut best) = trial(hi)?;
+    while outcome == Trial::TooLittle {
+        lo = hi;
+        let Some(next) = hi.checked_mul(2) else {
+            return Ok(None);
+        };
+        hi = next;
+        (outcome, best) = trial(hi)?;
+    }
+    while hi - lo > 1 && outcome != Trial::Failed {
+        let mid = lo + (hi - lo) / 2;
+        let (mid_outcome, r) = trial(mid)?;
+        if mid_outcome == Trial::TooLittle {
+            lo = mid;
+        } else {
+            hi = mid;
+            (outcome, best) = (mid_outcome, r);
+        }
+    }
+    Ok(Some(best))
+}
+
+// what the SolFi instruction consumed, from its `Program <id> consumed N of M compute units` line
+fn swap_compute_units(logs: &[String]) -> u64 {
+    let prefix = format!("Program {SOLFI_PROGRAM} consumed ");
+    logs.iter()
+        .rev()
+        .find_map(|line| line.strip_prefix(&prefix)?.split_once(' ')?.0.parse().ok())
+        .unwrap_or(0)
+}
+
+fn to_units(amount: f64, decimals: u8) -> eyre::Result<u64> {
+    let scale = 10u128.pow(decimals as u32);
+    let v = ((amount * scale as f64).round() as i128).max(0) as u128;
+    u64::try_from(v).map_err(|_| eyre!("{amount} does not fit in a token amount with {decimals} decimals"))
+}
+
+fn mk_ata_account(mint: &Pubkey, user: &Pubkey, amount: u64) -> Account {
+    mk_ata_account_with_owner(mint, user, amount, &spl_token::id())
+}
+
+// a WSOL "native" token account (is_native=Some(0)) so sync_native works inside LiteSVM
+fn mk_native_wsol_account(user: &Pubkey) -> Account {
+    let ata = TokenAccount {
+        mint: WSOL,
+        owner: *user,
+        amount: 0,
+        delegate: COption::None,
+        state: AccountState::Initialized,
+        is_native: COption::Some(0), // mark as native SOL wrapper
+        delegated_amount: 0,
+        close_authority: COption::None,
+    };
+    let mut data = vec![0u8; TokenAccount::LEN];
+    ata.pack_into_slice(&mut data);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: spl_token::id(),
+        executable: false,
+        rent_epoch: RENT_EXEMPT_RENT_EPOCH,
+    }
+}
+
+// create a token account with the correct owner program (spl-token or spl-token-2022)
+fn mk_ata_account_with_owner(
+    mint: &Pubkey,
+    user: &Pubkey,
+    amount: u64,
+    token_program: &Pubkey,
+) -> Account {
+    if *token_program == spl_token::id() {
+        // legacy SPL Token layout
+        let ata = TokenAccount {
+            mint: *mint,
+            owner: *user,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        };
+        let mut data = vec![0u8; TokenAccount::LEN];
+        ata.pack_into_slice(&mut data);
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: RENT_EXEMPT_RENT_EPOCH,
+        }
+    } else {
+        // Token-2022 account (no extensions for our sim)
+        let ata2022 = token2022_state::Account {
+            mint: *mint,
+            owner: *user,
+            amount,
+            delegate: COption::None,
+            state: token2022_state::AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        };
+        let mut data = vec![0u8; token2022_state::Account::LEN];
+        ata2022.pack_into_slice(&mut data);
+        Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: spl_token_2022::id(),
+            executable: false,
+            rent_epoch: RENT_EXEMPT_RENT_EPOCH,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_only_the_swap_instruction() {
+        let logs = [
+            "Program 11111111111111111111111111111111 invoke [1]".to_string(),
+            "Program 11111111111111111111111111111111 success".to_string(),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA consumed 3100 of 1399850 compute units".to_string(),
+            format!("Program {SOLFI_PROGRAM} invoke [1]"),
+            "Program TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA consumed 4645 of 1380000 compute units".to_string(),
+            format!("Program {SOLFI_PROGRAM} consumed 35408 of 1396750 compute units"),
+            format!("Program {SOLFI_PROGRAM} success"),
+        ];
+        assert_eq!(swap_compute_units(&logs), 35408);
+        assert_eq!(swap_compute_units(&logs[..3]), 0);
+    }
+
+    // trials of a pool paying a third of the input, up to `depth` input; past it the swap runs
+    // out of liquidity, and from `broken` on it fails outright
+    fn pool(target: u64, depth: u64, broken: u64) -> impl FnMut(u64) -> eyre::Result<(Trial, u64)> {
+        move |amount| {
+            let trial = if amount >= broken {
+                Trial::Failed
+            } else if amount > depth || amount / 3 >= target {
+                Trial::Enough
+            } else {
+                Trial::TooLittle
+            };
+            Ok((trial, amount))
+        }
+    }
+
+    #[test]
+    fn finds_the_smallest_input_that_is_enough() {
+        for start in [0, 1, 4, 30, 31, 1_000_000] {
+            assert_eq!(search_exact_out(start, pool(10, u64::MAX, u64::MAX)).unwrap(), Some(30), "{start}");
+        }
+    }
+
+    #[test]
+    fn treats_running_out_of_liquidity_as_too_much() {
+        assert_eq!(search_exact_out(1_000_000, pool(10, 50, u64::MAX)).unwrap(), Some(30));
+        // a pool too shallow for the target ends up at its depth
+        assert_eq!(search_exact_out(4, pool(100, 50, u64::MAX)).unwrap(), Some(51));
+    }
+
+    #[test]
+    fn stops_at_the_first_failure() {
+        let mut trials = 0;
+        let mut failing = pool(10, u64::MAX, 0);
+        let result = search_exact_out(4, |amount| {
+            trials += 1;
+            failing(amount)
+        });
+        assert_eq!((result.unwrap(), trials), (Some(4), 1));
+        // a failure while doubling or bisecting is reported rather than searched past
+        assert_eq!(search_exact_out(4, pool(10, u64::MAX, 25)).unwrap(), Some(32));
+        let mut flaky = pool(10, u64::MAX, u64::MAX);
+        let result = search_exact_out(40, |amount| match amount {
+            16..=20 => Ok((Trial::Failed, amount)),
+            _ => flaky(amount),
+        });
+        assert_eq!(result.unwrap(), Some(20));
+    }
+
+    #[test]
+    fn gives_up_when_doubling_overflows() {
+        assert_eq!(search_exact_out(1, pool(u64::MAX, u64::MAX, u64::MAX)).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_amounts_too_large_for_a_token_amount() {
+        assert_eq!(to_units(1.5, 6).unwrap(), 1_500_000);
+        assert!(to_units(f64::INFINITY, 6).is_err());
+        assert!(to_units(u64::MAX as f64, 9).is_err());
+    }
+}
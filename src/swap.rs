@@ -11,13 +11,23 @@ use std::fmt;
 
 const DISCRIMINATOR: u8 = 7;
 
-#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, ValueEnum)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash, Serialize, ValueEnum)]
 pub enum SwapDirection {
     #[default]
     SolToUsdc,
     UsdcToSol,
 }
 
+impl SwapDirection {
+    /// Quote per base of a trade that swapped `in_amount` for `out_amount` in this direction.
+    pub fn average_price(self, in_amount: f64, out_amount: f64) -> f64 {
+        match self {
+            SwapDirection::SolToUsdc => out_amount / in_amount,
+            SwapDirection::UsdcToSol => in_amount / out_amount,
+        }
+    }
+}
+
 impl fmt::Display for SwapDirection {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -36,31 +46,8 @@ fn create_instruction_data(direction: SwapDirection, amount_in: u64) -> Vec<u8>
     buffer
 }
 
-pub fn create_swap_ix(
-    direction: SwapDirection,
-    market: &Pubkey,
-    user: &Pubkey,
-    token_a: &Pubkey,
-    token_b: &Pubkey,
-    amount: u64,
-) -> Instruction {
-    Instruction {
-        program_id: SOLFI_PROGRAM,
-        accounts: vec![
-            AccountMeta::new(*user, true),
-            AccountMeta::new(*market, false),
-            AccountMeta::new(get_associated_token_address(market, token_a), false),
-            AccountMeta::new(get_associated_token_address(market, token_b), false),
-            AccountMeta::new(get_associated_token_address(user, token_a), false),
-            AccountMeta::new(get_associated_token_address(user, token_b), false),
-            AccountMeta::new_readonly(spl_token::id(), false),
-            AccountMeta::new_readonly(sysvar::instructions::id(), false),
-        ],
-        data: create_instruction_data(direction, amount),
-    }
-}
-
 // ADDED — explicit vaults + dynamic token program (works for Token-2022)
+#[allow(clippy::too_many_arguments)]
 pub fn create_swap_ix_generic_with_token_program(
     direction: SwapDirection,
     market: &Pubkey,
@@ -1,28 +1,95 @@
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use eyre::WrapErr;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use serde::{Deserialize, Serialize};
 use solana_account::Account;
+use solana_account_decoder_client_types::{UiAccount, UiAccountData, UiAccountEncoding};
 use solana_pubkey::Pubkey;
 use std::fs;
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-#[derive(Serialize, Deserialize)]
+/// Gzipped JSON array of accounts, an alternative to one file per account.
+pub const BUNDLE_FILE_NAME: &str = "accounts.json.gz";
+
+/// Serialized in the `solana account --output json` layout (base64 data); files written with the
+/// original byte-array layout are still read.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "KeyedUiAccount", try_from = "AccountFile")]
 pub struct AccountWithAddress {
     pub address: Pubkey,
     pub account: Account,
 }
 
+#[derive(Serialize, Deserialize)]
+struct KeyedUiAccount {
+    pubkey: String,
+    account: UiAccount,
+}
+
+#[derive(Deserialize)]
+struct LegacyAccountFile {
+    address: Pubkey,
+    account: Account,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AccountFile {
+    Keyed(KeyedUiAccount),
+    Legacy(LegacyAccountFile),
+}
+
+impl From<AccountWithAddress> for KeyedUiAccount {
+    fn from(value: AccountWithAddress) -> Self {
+        let account = value.account;
+        Self {
+            pubkey: value.address.to_string(),
+            account: UiAccount {
+                lamports: account.lamports,
+                space: Some(account.data.len() as u64),
+                data: UiAccountData::Binary(BASE64_STANDARD.encode(&account.data), UiAccountEncoding::Base64),
+                owner: account.owner.to_string(),
+                executable: account.executable,
+                rent_epoch: account.rent_epoch,
+            },
+        }
+    }
+}
+
+impl TryFrom<AccountFile> for AccountWithAddress {
+    type Error = String;
+
+    fn try_from(value: AccountFile) -> Result<Self, Self::Error> {
+        match value {
+            AccountFile::Keyed(keyed) => {
+                let address = Pubkey::from_str(&keyed.pubkey).map_err(|e| format!("invalid pubkey: {e}"))?;
+                let account = keyed
+                    .account
+                    .decode()
+                    .ok_or_else(|| format!("account {address} has undecodable data or owner"))?;
+                Ok(Self { address, account })
+            }
+            AccountFile::Legacy(legacy) => Ok(Self { address: legacy.address, account: legacy.account }),
+        }
+    }
+}
+
 impl AccountWithAddress {
     fn get_filename(&self) -> String {
         format!("account_{}.json", self.address)
     }
 
-    pub fn save_to_file(&self) -> eyre::Result<()> {
+    pub fn save_to_file(&self, data_dir: &Path) -> eyre::Result<()> {
         let filename = self.get_filename();
         let serialized = serde_json::to_string(self)?;
-        let data_dir = Path::new("data");
         if !data_dir.exists() {
-            fs::create_dir(data_dir)?;
+            fs::create_dir_all(data_dir)?;
         }
         let file_path = data_dir.join(filename);
         let mut file = File::create(file_path)?;
@@ -36,12 +103,27 @@ impl AccountWithAddress {
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
 
-        let account_with_address: Self = serde_json::from_str(&contents)?;
+        let account_with_address: Self = serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("failed to parse account file {}", path.display()))?;
         Ok(account_with_address)
     }
 
-    pub fn read_all() -> eyre::Result<Vec<Self>> {
-        let data_dir = Path::new("data");
+    /// Writes `accounts` into a single [`BUNDLE_FILE_NAME`] in `data_dir`.
+    pub fn save_bundle(accounts: &[Self], data_dir: &Path) -> eyre::Result<()> {
+        fs::create_dir_all(data_dir)?;
+        let mut encoder = GzEncoder::new(File::create(data_dir.join(BUNDLE_FILE_NAME))?, Compression::default());
+        serde_json::to_writer(&mut encoder, accounts)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    pub fn read_bundle(path: &Path) -> eyre::Result<Vec<Self>> {
+        let decoder = GzDecoder::new(File::open(path)?);
+        serde_json::from_reader(decoder).wrap_err_with(|| format!("failed to parse bundle {}", path.display()))
+    }
+
+    /// Reads every `account_*.json` file in `data_dir`, plus its bundle if there is one.
+    pub fn read_all(data_dir: &Path) -> eyre::Result<Vec<Self>> {
         if !data_dir.exists() {
             return Ok(vec![]);
         }
@@ -62,6 +144,74 @@ impl AccountWithAddress {
             }
         }
 
+        let bundle = data_dir.join(BUNDLE_FILE_NAME);
+        if bundle.is_file() {
+            accounts.extend(Self::read_bundle(&bundle)?);
+        }
+
         Ok(accounts)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn account() -> AccountWithAddress {
+        AccountWithAddress {
+            address: Pubkey::new_unique(),
+            account: Account {
+                lamports: 1_000,
+                data: vec![0, 1, 2, 254, 255],
+                owner: Pubkey::new_unique(),
+                executable: false,
+                rent_epoch: 7,
+            },
+        }
+    }
+
+    fn assert_same(a: &AccountWithAddress, b: &AccountWithAddress) {
+        assert_eq!(a.address, b.address);
+        assert_eq!(a.account, b.account);
+    }
+
+    #[test]
+    fn writes_base64_and_reads_it_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = account();
+        original.save_to_file(dir.path()).unwrap();
+
+        let path = dir.path().join(original.get_filename());
+        let written: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["pubkey"], original.address.to_string());
+        assert_eq!(written["account"]["data"], json!([BASE64_STANDARD.encode([0, 1, 2, 254, 255]), "base64"]));
+
+        assert_same(&AccountWithAddress::read_account(path).unwrap(), &original);
+    }
+
+    #[test]
+    fn reads_the_legacy_byte_array_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let original = account();
+        let legacy = json!({ "address": original.address, "account": original.account });
+        assert!(legacy["account"]["data"].is_array());
+        let path = dir.path().join(original.get_filename());
+        fs::write(&path, legacy.to_string()).unwrap();
+
+        assert_same(&AccountWithAddress::read_account(path).unwrap(), &original);
+    }
+
+    #[test]
+    fn bundles_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let accounts = [account(), account()];
+        AccountWithAddress::save_bundle(&accounts, dir.path()).unwrap();
+
+        let read = AccountWithAddress::read_all(dir.path()).unwrap();
+        assert_eq!(read.len(), accounts.len());
+        for (read, original) in read.iter().zip(&accounts) {
+            assert_same(read, original);
+        }
+    }
+}
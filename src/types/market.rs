@@ -0,0 +1,150 @@
+use crate::constants::SOLFI_PROGRAM;
+use crate::utils::{pubkey_at_offset, u32_at_offset, u64_at_offset};
+use solana_account::Account;
+use solana_pubkey::Pubkey;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+pub const MARKET_ACCOUNT_LEN: usize = 2800;
+
+const PRICE_OFFSET: usize = 456;
+const GENERATED_SLOT_OFFSET: usize = 464;
+const GENERATED_AT_OFFSET: usize = 472;
+const CUTOFF_SLOT_OFFSET: usize = 488;
+const BASE_MINT_OFFSET: usize = 2664;
+const QUOTE_MINT_OFFSET: usize = 2696;
+const BASE_DECIMALS_OFFSET: usize = 2728;
+const QUOTE_DECIMALS_OFFSET: usize = 2732;
+const BASE_VAULT_OFFSET: usize = 2736;
+const QUOTE_VAULT_OFFSET: usize = 2768;
+
+// the price field carries one decimal more than the quote mint: DH4x stores 2111371562 while
+// selling 10 SOL in the same snapshot returns 2111.17 USDC
+const PRICE_EXTRA_DECIMALS: i32 = 1;
+
+/// Byte ranges of the market account covered by [`SolfiMarket`], in ascending order.
+pub const MARKET_FIELDS: &[(&str, Range<usize>)] = &[
+    ("price", PRICE_OFFSET..PRICE_OFFSET + 8),
+    ("generated_slot", GENERATED_SLOT_OFFSET..GENERATED_SLOT_OFFSET + 8),
+    ("generated_at_ms", GENERATED_AT_OFFSET..GENERATED_AT_OFFSET + 8),
+    ("cutoff_slot", CUTOFF_SLOT_OFFSET..CUTOFF_SLOT_OFFSET + 8),
+    ("base_mint", BASE_MINT_OFFSET..BASE_MINT_OFFSET + 32),
+    ("quote_mint", QUOTE_MINT_OFFSET..QUOTE_MINT_OFFSET + 32),
+    ("base_decimals", BASE_DECIMALS_OFFSET..BASE_DECIMALS_OFFSET + 4),
+    ("quote_decimals", QUOTE_DECIMALS_OFFSET..QUOTE_DECIMALS_OFFSET + 4),
+    ("base_vault", BASE_VAULT_OFFSET..BASE_VAULT_OFFSET + 32),
+    ("quote_vault", QUOTE_VAULT_OFFSET..QUOTE_VAULT_OFFSET + 32),
+];
+
+/// The fields of a SolFi market account whose meaning is known.
+///
+/// The layout is not published; offsets were recovered from mainnet snapshots. Everything else in
+/// the account (curve parameters, market-maker state) is left undecoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SolfiMarket {
+    /// Reference price posted by the market maker per whole base token, in units of
+    /// 10^-(quote_decimals + 1) quote tokens; see [`SolfiMarket::price_ui`].
+    pub price: u64,
+    /// Slot the market maker generated the current quote at.
+    pub generated_slot: u64,
+    /// Unix time in milliseconds the current quote was generated at.
+    pub generated_at_ms: u64,
+    /// Last slot the current quote is valid for.
+    pub cutoff_slot: u64,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_decimals: u32,
+    pub quote_decimals: u32,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+}
+
+impl SolfiMarket {
+    pub fn decode(data: &[u8]) -> eyre::Result<Self> {
+        eyre::ensure!(
+            data.len() == MARKET_ACCOUNT_LEN,
+            "unexpected SolFi market account size: {} bytes (expected {MARKET_ACCOUNT_LEN})",
+            data.len()
+        );
+        let market = Self {
+            price: u64_at_offset(data, PRICE_OFFSET)?,
+            generated_slot: u64_at_offset(data, GENERATED_SLOT_OFFSET)?,
+            generated_at_ms: u64_at_offset(data, GENERATED_AT_OFFSET)?,
+            cutoff_slot: u64_at_offset(data, CUTOFF_SLOT_OFFSET)?,
+            base_mint: pubkey_at_offset(data, BASE_MINT_OFFSET)?,
+            quote_mint: pubkey_at_offset(data, QUOTE_MINT_OFFSET)?,
+            base_decimals: u32_at_offset(data, BASE_DECIMALS_OFFSET)?,
+            quote_decimals: u32_at_offset(data, QUOTE_DECIMALS_OFFSET)?,
+            base_vault: pubkey_at_offset(data, BASE_VAULT_OFFSET)?,
+            quote_vault: pubkey_at_offset(data, QUOTE_VAULT_OFFSET)?,
+        };
+        eyre::ensure!(
+            [market.base_mint, market.quote_mint, market.base_vault, market.quote_vault]
+                .iter()
+                .all(|pk| *pk != Pubkey::default()),
+            "SolFi market account has unset mints or vaults"
+        );
+        Ok(market)
+    }
+
+    pub fn from_account(account: &Account) -> eyre::Result<Self> {
+        eyre::ensure!(
+            account.owner == SOLFI_PROGRAM,
+            "account is owned by {}, not the SolFi program",
+            account.owner
+        );
+        Self::decode(&account.data)
+    }
+
+    /// Reference price in quote tokens per base token.
+    pub fn price_ui(&self) -> f64 {
+        self.price as f64 / 10f64.powi(self.quote_decimals as i32 + PRICE_EXTRA_DECIMALS)
+    }
+}
+
+impl Display for SolfiMarket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "price:           {} ({})", self.price_ui(), self.price)?;
+        writeln!(f, "generated slot:  {}", self.generated_slot)?;
+        writeln!(f, "generated at:    {} ms", self.generated_at_ms)?;
+        writeln!(f, "cutoff slot:     {}", self.cutoff_slot)?;
+        writeln!(f, "base mint:       {} ({} decimals)", self.base_mint, self.base_decimals)?;
+        writeln!(f, "quote mint:      {} ({} decimals)", self.quote_mint, self.quote_decimals)?;
+        writeln!(f, "base vault:      {}", self.base_vault)?;
+        write!(f, "quote vault:     {}", self.quote_vault)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{USDC, WSOL};
+    use crate::types::AccountWithAddress;
+    use solana_pubkey::pubkey;
+    use std::path::PathBuf;
+
+    #[test]
+    fn decodes_a_snapshot_market() {
+        let market = pubkey!("DH4xmaWDnTzKXehVaPSNy9tMKJxnYL5Mo5U3oTHFtNYJ");
+        let account =
+            AccountWithAddress::read_account(PathBuf::from(format!("data/account_{market}.json"))).unwrap();
+        let decoded = SolfiMarket::from_account(&account.account).unwrap();
+
+        assert_eq!((decoded.base_mint, decoded.quote_mint), (WSOL, USDC));
+        assert_eq!((decoded.base_decimals, decoded.quote_decimals), (9, 6));
+        assert!(decoded.cutoff_slot > decoded.generated_slot);
+        for vault in [decoded.base_vault, decoded.quote_vault] {
+            assert!(PathBuf::from(format!("data/account_{vault}.json")).is_file());
+        }
+        assert_eq!(decoded.price, 2_111_371_562);
+        // selling 10 SOL on this snapshot returns 2111.17 USDC
+        assert!((decoded.price_ui() - 211.117).abs() < 0.1, "price {}", decoded.price_ui());
+    }
+
+    #[test]
+    fn rejects_accounts_of_other_programs() {
+        let account = Account { data: vec![0; MARKET_ACCOUNT_LEN], ..Account::default() };
+        assert!(SolfiMarket::from_account(&account).is_err());
+        assert!(SolfiMarket::decode(&[0; 16]).is_err());
+    }
+}
@@ -1,15 +1,15 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 use std::fs;
-use std::path::PathBuf;
+use std::path::Path;
 
-const FILE_PATH: &str = "data/metadata.json";
+pub const METADATA_FILE_NAME: &str = "metadata.json";
 
 #[derive(Serialize, Deserialize)]
 pub struct FetchMetadata {
     slot: Option<u64>,
 
-    // backward-compatability
+    // every account was read at a context slot within this range
     slot_lower: u64,
     slot_upper: u64,
 }
@@ -19,8 +19,14 @@ impl FetchMetadata {
         Self { slot: Some(slot), slot_lower: slot, slot_upper: slot }
     }
 
-    pub fn read() -> Option<Self> {
-        let path = PathBuf::from(FILE_PATH);
+    /// Metadata for accounts read across several context slots; the snapshot is labelled with
+    /// the newest one.
+    pub fn with_range(slot_lower: u64, slot_upper: u64) -> Self {
+        Self { slot: Some(slot_upper), slot_lower, slot_upper }
+    }
+
+    pub fn read(data_dir: &Path) -> Option<Self> {
+        let path = data_dir.join(METADATA_FILE_NAME);
         if !path.exists() {
             return None;
         }
@@ -33,19 +39,21 @@ impl FetchMetadata {
         self.slot.unwrap_or(self.slot_lower)
     }
 
-    pub fn save_to_file(&self) -> eyre::Result<()> {
-        let path = PathBuf::from(FILE_PATH);
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-        fs::write(&path, serde_json::to_string(self)?)?;
+    /// Whether every account was read at the same slot.
+    pub fn is_single_slot(&self) -> bool {
+        self.slot_lower == self.slot_upper
+    }
+
+    pub fn save_to_file(&self, data_dir: &Path) -> eyre::Result<()> {
+        fs::create_dir_all(data_dir)?;
+        fs::write(data_dir.join(METADATA_FILE_NAME), serde_json::to_string(self)?)?;
         Ok(())
     }
 }
 
 impl Display for FetchMetadata {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        if self.slot_lower == self.slot_upper {
+        if self.is_single_slot() {
             write!(f, "fetched at slot {}", self.slot_lower)
         } else {
             write!(f, "fetched between slots {} and {}", self.slot_lower, self.slot_upper)
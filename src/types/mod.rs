@@ -1,5 +1,18 @@
 mod account;
+mod market;
 mod metadata;
+mod program;
+mod pubkey_str;
+mod recording;
+mod registry;
+mod snapshot;
 
-pub use account::AccountWithAddress;
-pub use metadata::FetchMetadata;
+pub use account::{AccountWithAddress, BUNDLE_FILE_NAME};
+pub use market::{MARKET_ACCOUNT_LEN, MARKET_FIELDS, SolfiMarket};
+pub use metadata::{FetchMetadata, METADATA_FILE_NAME};
+pub use recording::{RECORDINGS_DIR, RecordedAccount, Recording, RecordingWriter};
+pub use registry::{MarketEntry, MarketRegistry, pair_symbol};
+pub use snapshot::{
+    FetchSource, LEGACY_SNAPSHOT_DIR, MANIFEST_FILE_NAME, ManifestAccount, SNAPSHOTS_DIR, Snapshot,
+    SnapshotFormat, SnapshotManifest, SnapshotProgram,
+};
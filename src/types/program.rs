@@ -0,0 +1,28 @@
+use crate::constants::SOLFI_PROGRAM_PATH;
+use std::path::{Path, PathBuf};
+
+/// Name of the program binary a snapshot or recording stores alongside its accounts.
+pub(crate) const PROGRAM_FILE: &str = "solfi.so";
+
+/// The `solfi.so` stored in `dir`, else `data/solfi.so`.
+pub(crate) fn program_path(dir: &Path) -> PathBuf {
+    let path = dir.join(PROGRAM_FILE);
+    if path.is_file() { path } else { SOLFI_PROGRAM_PATH.into() }
+}
+
+/// Warns when `what` (e.g. "snapshot <name>") has no program of its own in `dir` and falls back to
+/// `data/solfi.so`, which nothing keeps in step with the markets, or when its program was deployed
+/// at `deploy_slot`, after `state_slot`, the oldest market state it holds.
+pub(crate) fn warn_on_stale_program(what: &str, dir: &Path, deploy_slot: Option<u64>, state_slot: u64) {
+    if !program_path(dir).starts_with(dir) {
+        tracing::warn!(
+            "{what} has no program binary of its own; {SOLFI_PROGRAM_PATH} may be older than its \
+             market state"
+        );
+    } else if let Some(deploy_slot) = deploy_slot.filter(|slot| *slot > state_slot) {
+        tracing::warn!(
+            "the program in {what} was deployed at slot {deploy_slot}, after its market state at \
+             slot {state_slot}; states before the deploy ran under an older program"
+        );
+    }
+}
@@ -0,0 +1,14 @@
+//! Serializes a [`Pubkey`] as its base58 string instead of a byte array.
+
+use serde::{Deserialize, Deserializer, Serializer, de};
+use solana_pubkey::Pubkey;
+use std::str::FromStr;
+
+pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(pubkey)
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Pubkey::from_str(&s).map_err(de::Error::custom)
+}
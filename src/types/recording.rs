@@ -0,0 +1,258 @@
+use crate::types::AccountWithAddress;
+use crate::types::program::{PROGRAM_FILE, program_path, warn_on_stale_program};
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub const RECORDINGS_DIR: &str = "recordings";
+
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_SUFFIX: &str = ".jsonl";
+const PROGRAM_INFO_FILE: &str = "program.json";
+
+#[derive(Serialize, Deserialize)]
+struct ProgramInfo {
+    deploy_slot: u64,
+}
+
+/// One account state as seen at `slot`, a line of a recording segment.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecordedAccount {
+    pub slot: u64,
+    #[serde(flatten)]
+    pub account: AccountWithAddress,
+}
+
+/// An append-only series of account states in `recordings/<label>/`, split into
+/// `segment-<slot>.jsonl` files. Every segment starts with the full state of every recorded
+/// account, so each one can be replayed on its own once older segments are dropped.
+pub struct Recording {
+    dir: PathBuf,
+}
+
+impl Recording {
+    pub fn new(label: &str) -> Self {
+        Self { dir: Path::new(RECORDINGS_DIR).join(label) }
+    }
+
+    /// Opens a recording by label under `recordings/`, or by path.
+    pub fn open(name: &str) -> eyre::Result<Self> {
+        let path = Path::new(name);
+        let dir = if path.is_dir() { path.to_path_buf() } else { Path::new(RECORDINGS_DIR).join(name) };
+        eyre::ensure!(dir.is_dir(), "recording {name} not found in {RECORDINGS_DIR}/");
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The `solfi.so` fetched when recording started, else `data/solfi.so`.
+    pub fn program_path(&self) -> PathBuf {
+        program_path(&self.dir)
+    }
+
+    /// Stores the program binary the recording was made against, with the slot it was deployed at.
+    pub fn save_program(&self, program: &[u8], deploy_slot: u64) -> eyre::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(PROGRAM_FILE), program)?;
+        fs::write(self.dir.join(PROGRAM_INFO_FILE), serde_json::to_string(&ProgramInfo { deploy_slot })?)?;
+        Ok(())
+    }
+
+    /// Warns when replaying from `first_slot` on falls back to `data/solfi.so`, which may be older
+    /// than the recorded market state, or when the stored program was deployed after it, so the
+    /// earlier states ran under a program the recording does not have.
+    pub fn verify_program(&self, first_slot: u64) {
+        let deploy_slot = fs::read_to_string(self.dir.join(PROGRAM_INFO_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str::<ProgramInfo>(&content).ok())
+            .map(|info| info.deploy_slot);
+        warn_on_stale_program(&format!("recording {}", self.dir.display()), &self.dir, deploy_slot, first_slot);
+    }
+
+    /// Segment paths with their first slot, oldest first.
+    pub fn segments(&self) -> eyre::Result<Vec<(u64, PathBuf)>> {
+        let mut segments = vec![];
+        if !self.dir.is_dir() {
+            return Ok(segments);
+        }
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let slot = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix(SEGMENT_PREFIX)?.strip_suffix(SEGMENT_SUFFIX))
+                .and_then(|slot| slot.parse::<u64>().ok());
+            if let Some(slot) = slot {
+                segments.push((slot, path));
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Every recorded state in the order it was written. A line cut short by an interrupted write
+    /// at the end of a segment is skipped.
+    pub fn read_all(&self) -> eyre::Result<Vec<RecordedAccount>> {
+        let mut records = vec![];
+        for (_, path) in self.segments()? {
+            let lines = BufReader::new(File::open(&path)?).lines().collect::<Result<Vec<_>, _>>()?;
+            let last = lines.len().saturating_sub(1);
+            for (i, line) in lines.iter().enumerate() {
+                match serde_json::from_str(line) {
+                    Ok(record) => records.push(record),
+                    Err(_) if i == last => tracing::warn!("ignoring truncated last line of {}", path.display()),
+                    Err(e) => return Err(e).wrap_err_with(|| format!("{}:{}", path.display(), i + 1)),
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// Appends to a [`Recording`], rotating to a new segment past `max_segment_bytes` and deleting the
+/// oldest segments once the recording outgrows `max_total_bytes`.
+pub struct RecordingWriter {
+    recording: Recording,
+    max_segment_bytes: u64,
+    max_total_bytes: u64,
+    segment: Option<(BufWriter<File>, u64)>,
+}
+
+impl RecordingWriter {
+    pub fn new(recording: Recording, max_segment_bytes: u64, max_total_bytes: u64) -> eyre::Result<Self> {
+        fs::create_dir_all(recording.dir())?;
+        Ok(Self { recording, max_segment_bytes, max_total_bytes, segment: None })
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Whether the next [`RecordingWriter::append`] starts a new segment, which then has to be
+    /// given the full state rather than only the changes.
+    pub fn needs_new_segment(&self) -> bool {
+        self.segment.as_ref().is_none_or(|(_, len)| *len >= self.max_segment_bytes)
+    }
+
+    /// Appends `records` (all from one poll) and flushes them.
+    pub fn append(&mut self, slot: u64, records: &[RecordedAccount]) -> eyre::Result<()> {
+        if self.needs_new_segment() {
+            self.rotate(slot)?;
+        }
+        let (file, len) = self.segment.as_mut().expect("rotate opens a segment");
+        for record in records {
+            let line = serde_json::to_string(record)? + "\n";
+            file.write_all(line.as_bytes())?;
+            *len += line.len() as u64;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    fn rotate(&mut self, slot: u64) -> eyre::Result<()> {
+        if let Some((mut file, _)) = self.segment.take() {
+            file.flush()?;
+        }
+        let path = self.recording.dir().join(format!("{SEGMENT_PREFIX}{slot}{SEGMENT_SUFFIX}"));
+        let file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        let len = file.metadata()?.len();
+        self.segment = Some((BufWriter::new(file), len));
+        self.enforce_cap(&path)
+    }
+
+    fn enforce_cap(&self, current: &Path) -> eyre::Result<()> {
+        let segments = self.recording.segments()?;
+        let mut total = segments.iter().map(|(_, p)| fs::metadata(p).map(|m| m.len()).unwrap_or(0)).sum::<u64>();
+        for (_, path) in segments {
+            if total <= self.max_total_bytes || path == current {
+                break;
+            }
+            total -= fs::metadata(&path)?.len();
+            fs::remove_file(&path)?;
+            tracing::info!("Deleted {} to stay under the size cap", path.display());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account::Account;
+    use solana_pubkey::Pubkey;
+
+    fn record(slot: u64) -> RecordedAccount {
+        let account = Account { lamports: 1, data: vec![7; 32], ..Account::default() };
+        RecordedAccount { slot, account: AccountWithAddress { address: Pubkey::new_unique(), account } }
+    }
+
+    // records serialize to about this length; only the address differs, by a character or so
+    fn line_len() -> u64 {
+        serde_json::to_string(&record(1)).unwrap().len() as u64 + 1
+    }
+
+    fn recording(dir: &tempfile::TempDir) -> Recording {
+        Recording::open(dir.path().to_str().unwrap()).unwrap()
+    }
+
+    fn segment_slots(recording: &Recording) -> Vec<u64> {
+        recording.segments().unwrap().into_iter().map(|(slot, _)| slot).collect()
+    }
+
+    #[test]
+    fn rotates_once_a_segment_reaches_its_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = RecordingWriter::new(recording(&dir), 3 * line_len() / 2, u64::MAX).unwrap();
+        assert!(writer.needs_new_segment());
+
+        writer.append(1, &[record(1)]).unwrap();
+        assert!(!writer.needs_new_segment());
+        writer.append(2, &[record(2)]).unwrap();
+        assert!(writer.needs_new_segment());
+        writer.append(3, &[record(3)]).unwrap();
+
+        assert_eq!(segment_slots(writer.recording()), [1, 3]);
+        let slots = writer.recording().read_all().unwrap().iter().map(|r| r.slot).collect::<Vec<_>>();
+        assert_eq!(slots, [1, 2, 3]);
+    }
+
+    #[test]
+    fn deletes_the_oldest_segments_past_the_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = RecordingWriter::new(recording(&dir), 1, 3 * line_len() / 2).unwrap();
+        for slot in 1..=4 {
+            writer.append(slot, &[record(slot)]).unwrap();
+        }
+        assert_eq!(segment_slots(writer.recording()), [3, 4]);
+    }
+
+    #[test]
+    fn never_deletes_the_current_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = RecordingWriter::new(recording(&dir), 1, 0).unwrap();
+        for slot in 1..=3 {
+            writer.append(slot, &[record(slot)]).unwrap();
+            assert_eq!(segment_slots(writer.recording()), [slot]);
+        }
+        assert_eq!(writer.recording().read_all().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn skips_a_truncated_last_line_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let recording = recording(&dir);
+        let lines = [record(1), record(2)].map(|r| serde_json::to_string(&r).unwrap());
+        let path = dir.path().join("segment-1.jsonl");
+
+        fs::write(&path, format!("{}\n{}\n{}", lines[0], lines[1], &lines[1][..20])).unwrap();
+        assert_eq!(recording.read_all().unwrap().len(), 2);
+
+        fs::write(&path, format!("{}\n{}\n{}\n", lines[0], &lines[1][..20], lines[1])).unwrap();
+        assert!(recording.read_all().is_err());
+    }
+}
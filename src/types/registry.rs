@@ -0,0 +1,91 @@
+use crate::constants::{KNOWN_MINTS, SOLFI_MARKETS, USDC, WSOL};
+use crate::types::pubkey_str;
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use solana_pubkey::Pubkey;
+use std::fs;
+use std::path::PathBuf;
+
+const FILE_PATH: &str = "data/markets.json";
+
+/// A SolFi market as recorded by `discover-markets`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MarketEntry {
+    #[serde(with = "pubkey_str")]
+    pub market: Pubkey,
+    pub pair: String,
+    #[serde(with = "pubkey_str")]
+    pub base_mint: Pubkey,
+    #[serde(with = "pubkey_str")]
+    pub quote_mint: Pubkey,
+    #[serde(with = "pubkey_str")]
+    pub base_vault: Pubkey,
+    #[serde(with = "pubkey_str")]
+    pub quote_vault: Pubkey,
+    #[serde(with = "pubkey_str")]
+    pub token_program: Pubkey,
+}
+
+/// Local registry of every known SolFi market, written to `data/markets.json`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct MarketRegistry {
+    pub markets: Vec<MarketEntry>,
+}
+
+impl MarketRegistry {
+    /// The registry `discover-markets` saved, or `None` if it has not been run. A registry that
+    /// does not parse is an error rather than silently replaced by [`SOLFI_MARKETS`].
+    pub fn read() -> eyre::Result<Option<Self>> {
+        let path = PathBuf::from(FILE_PATH);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        let registry = serde_json::from_str(&content)
+            .wrap_err_with(|| format!("failed to parse {FILE_PATH}; re-run discover-markets"))?;
+        Ok(Some(registry))
+    }
+
+    pub fn save_to_file(&self) -> eyre::Result<()> {
+        let path = PathBuf::from(FILE_PATH);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn find(&self, market: &Pubkey) -> Option<&MarketEntry> {
+        self.markets.iter().find(|m| m.market == *market)
+    }
+
+    pub fn pools(&self, base_mint: &Pubkey, quote_mint: &Pubkey) -> Vec<&MarketEntry> {
+        self.markets
+            .iter()
+            .filter(|m| m.base_mint == *base_mint && m.quote_mint == *quote_mint)
+            .collect()
+    }
+
+    /// The WSOL/USDC pools from the local registry, or [`SOLFI_MARKETS`] when no registry has
+    /// been discovered (or it lists none).
+    pub fn wsol_usdc_markets() -> eyre::Result<Vec<Pubkey>> {
+        let discovered = Self::read()?
+            .map(|r| r.pools(&WSOL, &USDC).iter().map(|m| m.market).collect::<Vec<_>>())
+            .unwrap_or_default();
+        Ok(if discovered.is_empty() { SOLFI_MARKETS.to_vec() } else { discovered })
+    }
+}
+
+/// Human-readable pair name such as `SOL/USDC`, falling back to a shortened mint address for
+/// tokens not in [`KNOWN_MINTS`].
+pub fn pair_symbol(base_mint: &Pubkey, quote_mint: &Pubkey) -> String {
+    format!("{}/{}", mint_symbol(base_mint), mint_symbol(quote_mint))
+}
+
+fn mint_symbol(mint: &Pubkey) -> String {
+    KNOWN_MINTS
+        .iter()
+        .find(|(pk, _)| pk == mint)
+        .map(|(_, symbol)| symbol.to_string())
+        .unwrap_or_else(|| mint.to_string()[..4].to_string())
+}
@@ -0,0 +1,456 @@
+use crate::types::program::{PROGRAM_FILE, program_path, warn_on_stale_program};
+use crate::types::{AccountWithAddress, BUNDLE_FILE_NAME, FetchMetadata, pubkey_str};
+use crate::utils::{is_elf, redact_url, sha256_hex};
+use serde::{Deserialize, Serialize};
+use solana_pubkey::Pubkey;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+/// Flat directory older versions fetched into; used when no versioned snapshot exists yet.
+pub const LEGACY_SNAPSHOT_DIR: &str = "data";
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// How a snapshot stores its accounts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SnapshotFormat {
+    /// One `account_<address>.json` per account.
+    #[default]
+    Files,
+    /// Every account in one gzipped `accounts.json.gz`.
+    Bundle,
+}
+
+/// A SolFi program binary stored with a snapshot.
+pub struct SnapshotProgram<'a> {
+    pub elf: &'a [u8],
+    /// Slot the program was last deployed at, when it was fetched from chain.
+    pub deploy_slot: Option<u64>,
+}
+
+/// Where a snapshot was fetched from.
+pub struct FetchSource<'a> {
+    pub rpc_url: &'a str,
+    pub commitment: &'a str,
+}
+
+impl FetchSource<'static> {
+    /// Accounts imported from files rather than fetched over RPC.
+    pub const IMPORTED: Self = Self { rpc_url: "import", commitment: "unknown" };
+}
+
+/// Provenance and integrity record written last into every snapshot directory, so a missing
+/// manifest also means the fetch never finished writing.
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub label: String,
+    /// RPC URL with credentials, path and query stripped.
+    pub rpc_url: String,
+    pub commitment: String,
+    pub context_slot: u64,
+    /// RFC 3339 UTC time the fetch completed.
+    pub fetched_at: String,
+    /// SHA-256 of the snapshot's own `solfi.so`, or of `data/solfi.so` when it has none.
+    pub program_sha256: Option<String>,
+    /// Slot the snapshot's own `solfi.so` was last deployed at on chain.
+    #[serde(default)]
+    pub program_deploy_slot: Option<u64>,
+    pub accounts: Vec<ManifestAccount>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestAccount {
+    #[serde(with = "pubkey_str")]
+    pub address: Pubkey,
+    #[serde(with = "pubkey_str")]
+    pub owner: Pubkey,
+    pub data_len: usize,
+    /// SHA-256 of the account data.
+    pub sha256: String,
+}
+
+impl ManifestAccount {
+    fn new(account: &AccountWithAddress) -> Self {
+        Self {
+            address: account.address,
+            owner: account.account.owner,
+            data_len: account.account.data.len(),
+            sha256: sha256_hex(&account.account.data),
+        }
+    }
+
+    fn mismatch(&self, account: &AccountWithAddress) -> Option<String> {
+        let actual = Self::new(account);
+        if actual.owner != self.owner {
+            Some(format!("{}: owner {} (manifest: {})", self.address, actual.owner, self.owner))
+        } else if actual.data_len != self.data_len {
+            Some(format!("{}: {} bytes (manifest: {})", self.address, actual.data_len, self.data_len))
+        } else if actual.sha256 != self.sha256 {
+            Some(format!("{}: data hash does not match the manifest", self.address))
+        } else {
+            None
+        }
+    }
+}
+
+/// A directory of fetched accounts, `snapshots/<slot>-<label>/`.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    dir: PathBuf,
+    // set once the missing manifest has been warned about, so one load warns once
+    warned_unverified: OnceLock<()>,
+}
+
+impl Snapshot {
+    /// Writes `accounts` and `metadata` into a new `snapshots/<slot>-<label>/` directory along
+    /// with its manifest. A `program` is stored with the snapshot and used instead of
+    /// `data/solfi.so` when it is loaded.
+    pub fn save(
+        label: &str,
+        accounts: &[AccountWithAddress],
+        metadata: &FetchMetadata,
+        source: &FetchSource,
+        format: SnapshotFormat,
+        program: Option<&SnapshotProgram>,
+    ) -> eyre::Result<Self> {
+        Self::save_in(Path::new(SNAPSHOTS_DIR), label, accounts, metadata, source, format, program)
+    }
+
+    fn save_in(
+        root: &Path,
+        label: &str,
+        accounts: &[AccountWithAddress],
+        metadata: &FetchMetadata,
+        source: &FetchSource,
+        format: SnapshotFormat,
+        program: Option<&SnapshotProgram>,
+    ) -> eyre::Result<Self> {
+        if let Some(program) = program {
+            eyre::ensure!(is_elf(program.elf), "program binary is not an ELF file");
+        }
+        let slot = metadata.slot();
+        let dir = root.join(format!("{slot}-{label}"));
+        // a re-fetch at the same slot replaces the old snapshot rather than mixing with it
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+
+        match format {
+            SnapshotFormat::Files => {
+                for account in accounts {
+                    account.save_to_file(&dir)?;
+                }
+            }
+            SnapshotFormat::Bundle => AccountWithAddress::save_bundle(accounts, &dir)?,
+        }
+        metadata.save_to_file(&dir)?;
+        if let Some(program) = program {
+            fs::write(dir.join(PROGRAM_FILE), program.elf)?;
+        }
+        let snapshot = Self::at(dir);
+
+        let manifest = SnapshotManifest {
+            label: label.to_string(),
+            rpc_url: redact_url(source.rpc_url),
+            commitment: source.commitment.to_string(),
+            context_slot: slot,
+            fetched_at: chrono::Utc::now().to_rfc3339(),
+            program_sha256: fs::read(snapshot.program_path()).ok().map(|program| sha256_hex(&program)),
+            program_deploy_slot: program.and_then(|p| p.deploy_slot),
+            accounts: accounts.iter().map(ManifestAccount::new).collect(),
+        };
+        fs::write(snapshot.dir.join(MANIFEST_FILE_NAME), serde_json::to_string_pretty(&manifest)?)?;
+
+        Ok(snapshot)
+    }
+
+    /// Opens a snapshot by directory name under `snapshots/`, or by path.
+    pub fn open(name: &str) -> eyre::Result<Self> {
+        let path = Path::new(name);
+        if path.is_dir() {
+            return Ok(Self::at(path.to_path_buf()));
+        }
+        let dir = Path::new(SNAPSHOTS_DIR).join(name);
+        eyre::ensure!(dir.is_dir(), "snapshot {name} not found in {SNAPSHOTS_DIR}/");
+        Ok(Self::at(dir))
+    }
+
+    fn at(dir: PathBuf) -> Self {
+        Self { dir, warned_unverified: OnceLock::new() }
+    }
+
+    /// The snapshot with the highest slot, falling back to the legacy `data/` directory. Snapshots
+    /// fetched at the same slot are ordered by when they were written. Directories without a
+    /// manifest, left by a fetch that did not finish, are skipped with a warning.
+    pub fn latest() -> eyre::Result<Self> {
+        let dir = Self::latest_in(Path::new(SNAPSHOTS_DIR))?;
+        Ok(Self::at(dir.unwrap_or_else(|| LEGACY_SNAPSHOT_DIR.into())))
+    }
+
+    fn latest_in(root: &Path) -> eyre::Result<Option<PathBuf>> {
+        if !root.is_dir() {
+            return Ok(None);
+        }
+        let mut latest: Option<((u64, SystemTime), PathBuf)> = None;
+        for entry in fs::read_dir(root)? {
+            let path = entry?.path();
+            let Some(slot) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.split_once('-'))
+                .and_then(|(slot, _)| slot.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            if !path.is_dir() {
+                continue;
+            }
+            if !path.join(MANIFEST_FILE_NAME).is_file() {
+                tracing::warn!(
+                    "skipping {}: it has no manifest, so the fetch that wrote it did not finish",
+                    path.display()
+                );
+                continue;
+            }
+            let key = (slot, fs::metadata(&path)?.modified()?);
+            if latest.as_ref().is_none_or(|(latest_key, _)| key > *latest_key) {
+                latest = Some((key, path));
+            }
+        }
+        Ok(latest.map(|(_, path)| path))
+    }
+
+    /// The named snapshot, or the latest one when `name` is `None`.
+    pub fn resolve(name: Option<&str>) -> eyre::Result<Self> {
+        match name {
+            Some(name) => Self::open(name),
+            None => Self::latest(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn name(&self) -> String {
+        self.dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+
+    /// The snapshot's own `solfi.so` if it has one, else `data/solfi.so`.
+    pub fn program_path(&self) -> PathBuf {
+        program_path(&self.dir)
+    }
+
+    pub fn account_path(&self, address: &Pubkey) -> PathBuf {
+        self.dir.join(format!("account_{address}.json"))
+    }
+
+    /// Reads one account, checking it against the manifest.
+    pub fn read_account(&self, address: &Pubkey) -> eyre::Result<AccountWithAddress> {
+        let path = self.account_path(address);
+        let bundle = self.dir.join(BUNDLE_FILE_NAME);
+        let account = if !path.exists() && bundle.exists() {
+            AccountWithAddress::read_bundle(&bundle)?
+                .into_iter()
+                .find(|a| a.address == *address)
+                .ok_or_else(|| eyre::eyre!("account {address} is not in snapshot {}", self.name()))?
+        } else {
+            AccountWithAddress::read_account(path)?
+        };
+        if let Some(manifest) = self.verified_manifest()? {
+            let entry = manifest.accounts.iter().find(|a| a.address == *address);
+            let problem = match entry {
+                Some(entry) => entry.mismatch(&account),
+                None => Some(format!("{address}: not listed in the manifest")),
+            };
+            if let Some(problem) = problem {
+                eyre::bail!("snapshot {} failed verification: {problem}", self.name());
+            }
+        }
+        Ok(account)
+    }
+
+    /// Reads every account, refusing to return them if any file is missing, unexpected or does
+    /// not match the manifest.
+    pub fn read_accounts(&self) -> eyre::Result<Vec<AccountWithAddress>> {
+        let accounts = AccountWithAddress::read_all(&self.dir)?;
+        let Some(manifest) = self.verified_manifest()? else {
+            return Ok(accounts);
+        };
+
+        let expected = manifest.accounts.iter().map(|a| (a.address, a)).collect::<BTreeMap<_, _>>();
+        let present = accounts.iter().map(|a| a.address).collect::<BTreeSet<_>>();
+        let mut problems = vec![];
+        for account in &accounts {
+            match expected.get(&account.address) {
+                Some(entry) => problems.extend(entry.mismatch(account)),
+                None => problems.push(format!("{}: not listed in the manifest", account.address)),
+            }
+        }
+        for address in expected.keys().filter(|address| !present.contains(address)) {
+            problems.push(format!("{address}: missing"));
+        }
+        eyre::ensure!(
+            problems.is_empty(),
+            "snapshot {} failed verification:\n  {}",
+            self.name(),
+            problems.join("\n  ")
+        );
+
+        Ok(accounts)
+    }
+
+    /// Warns when `program` is not the `solfi.so` the snapshot was fetched with, when the
+    /// snapshot has no program of its own and falls back to `data/solfi.so`, which nothing keeps
+    /// in step with the markets, or when its program was deployed after its accounts were read.
+    pub fn verify_program(&self, program: &[u8]) {
+        let Some(manifest) = self.manifest() else {
+            return;
+        };
+        warn_on_stale_program(
+            &format!("snapshot {}", self.name()),
+            &self.dir,
+            manifest.program_deploy_slot,
+            manifest.context_slot,
+        );
+        if let Some(expected) = manifest.program_sha256 {
+            let actual = sha256_hex(program);
+            if actual != expected {
+                tracing::warn!(
+                    "{} (sha256 {actual}) differs from the one snapshot {} was \
+                     fetched with (sha256 {expected})",
+                    self.program_path().display(),
+                    self.name()
+                );
+            }
+        }
+    }
+
+    pub fn metadata(&self) -> Option<FetchMetadata> {
+        FetchMetadata::read(&self.dir)
+    }
+
+    pub fn manifest(&self) -> Option<SnapshotManifest> {
+        let content = fs::read_to_string(self.dir.join(MANIFEST_FILE_NAME)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// The manifest to verify against. Snapshots under `snapshots/` must have one; other
+    /// directories (the legacy `data/`, or ad-hoc paths) are read unverified.
+    fn verified_manifest(&self) -> eyre::Result<Option<SnapshotManifest>> {
+        let path = self.dir.join(MANIFEST_FILE_NAME);
+        if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            let manifest = serde_json::from_str(&content)
+                .map_err(|e| eyre::eyre!("snapshot {} has an unreadable manifest: {e}", self.name()))?;
+            return Ok(Some(manifest));
+        }
+        eyre::ensure!(
+            !is_within(&self.dir, Path::new(SNAPSHOTS_DIR)),
+            "snapshot {} has no manifest; the fetch that wrote it did not finish",
+            self.name()
+        );
+        self.warned_unverified.get_or_init(|| {
+            tracing::warn!("{} has no manifest; reading it without verification", self.dir.display());
+        });
+        Ok(None)
+    }
+}
+
+// whether `dir` is inside `root`, however either is spelled (relative, `./`, absolute, symlinked)
+fn is_within(dir: &Path, root: &Path) -> bool {
+    match (fs::canonicalize(dir), fs::canonicalize(root)) {
+        (Ok(dir), Ok(root)) => dir.starts_with(root),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account::Account;
+
+    fn account(data: Vec<u8>) -> AccountWithAddress {
+        AccountWithAddress {
+            address: Pubkey::new_unique(),
+            account: Account { lamports: 1, data, owner: Pubkey::new_unique(), ..Account::default() },
+        }
+    }
+
+    fn save(root: &Path, accounts: &[AccountWithAddress], format: SnapshotFormat) -> Snapshot {
+        let metadata = FetchMetadata::new(42);
+        Snapshot::save_in(root, "test", accounts, &metadata, &FetchSource::IMPORTED, format, None).unwrap()
+    }
+
+    #[test]
+    fn reads_back_what_it_saved() {
+        let root = tempfile::tempdir().unwrap();
+        let accounts = [account(vec![1, 2, 3]), account(vec![4, 5])];
+        for format in [SnapshotFormat::Files, SnapshotFormat::Bundle] {
+            let snapshot = save(root.path(), &accounts, format);
+            assert_eq!(snapshot.read_accounts().unwrap().len(), 2);
+            let read = snapshot.read_account(&accounts[1].address).unwrap();
+            assert_eq!(read.account, accounts[1].account);
+        }
+    }
+
+    #[test]
+    fn refuses_a_tampered_account() {
+        let root = tempfile::tempdir().unwrap();
+        let accounts = [account(vec![1, 2, 3]), account(vec![4, 5])];
+        let snapshot = save(root.path(), &accounts, SnapshotFormat::Files);
+
+        let mut tampered = accounts[0].clone();
+        tampered.account.data[0] = 9;
+        tampered.save_to_file(snapshot.dir()).unwrap();
+
+        let err = snapshot.read_accounts().map(|a| a.len()).unwrap_err().to_string();
+        assert!(err.contains("data hash does not match"), "{err}");
+        assert!(snapshot.read_account(&accounts[0].address).is_err());
+        assert!(snapshot.read_account(&accounts[1].address).is_ok());
+    }
+
+    #[test]
+    fn refuses_a_missing_account() {
+        let root = tempfile::tempdir().unwrap();
+        let accounts = [account(vec![1, 2, 3]), account(vec![4, 5])];
+        let snapshot = save(root.path(), &accounts, SnapshotFormat::Files);
+
+        fs::remove_file(snapshot.account_path(&accounts[1].address)).unwrap();
+
+        let err = snapshot.read_accounts().map(|a| a.len()).unwrap_err().to_string();
+        assert!(err.contains(&format!("{}: missing", accounts[1].address)), "{err}");
+    }
+
+    #[test]
+    fn latest_skips_an_unfinished_fetch() {
+        let root = tempfile::tempdir().unwrap();
+        let finished = save(root.path(), &[account(vec![1])], SnapshotFormat::Files);
+        // a later fetch interrupted before it wrote its manifest
+        let unfinished = root.path().join("43-test");
+        fs::create_dir(&unfinished).unwrap();
+        account(vec![2]).save_to_file(&unfinished).unwrap();
+
+        assert_eq!(Snapshot::latest_in(root.path()).unwrap(), Some(finished.dir().to_path_buf()));
+        fs::remove_file(finished.dir().join(MANIFEST_FILE_NAME)).unwrap();
+        assert_eq!(Snapshot::latest_in(root.path()).unwrap(), None);
+    }
+
+    #[test]
+    fn recognizes_directories_inside_a_root_however_spelled() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("42-test");
+        fs::create_dir(&dir).unwrap();
+
+        assert!(is_within(&dir, root.path()));
+        assert!(is_within(&root.path().join(".").join("42-test"), root.path()));
+        assert!(is_within(&root.path().join("42-test/../42-test"), &root.path().join(".")));
+        assert!(!is_within(root.path(), &dir));
+        assert!(!is_within(&root.path().join("missing"), root.path()));
+    }
+}